@@ -0,0 +1,58 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::cell::Cell;
+use timetrax::{business_logic, database};
+
+struct BenchTime(Cell<DateTime<Utc>>);
+
+impl database::TimeProvider for BenchTime {
+    fn now(&self) -> DateTime<Utc> {
+        self.0.get()
+    }
+}
+
+const YEARS: i64 = 5;
+
+/// Work from 9 to 17 with a lunch break on every weekday
+fn fill(db: &database::Database<BenchTime>, time: &BenchTime) {
+    db.add_work_item("bench").unwrap();
+    let work_item = db.get_available_work().unwrap()[0].1;
+    let start = time.0.get();
+    for day in 0..YEARS * 365 {
+        let day_start = start + Duration::days(day);
+        if day_start.weekday().number_from_monday() > 5 {
+            continue;
+        }
+        for (hours, work) in [
+            (0, Some(work_item)),
+            (3, None),
+            (4, Some(work_item)),
+            (8, None),
+        ] {
+            time.0.set(day_start + Duration::hours(hours));
+            db.set_current_work(work).unwrap();
+        }
+    }
+    time.0.set(start + Duration::days(YEARS * 365));
+}
+
+fn balance(c: &mut Criterion) {
+    let time = BenchTime(Cell::new(
+        Utc.with_ymd_and_hms(2000, 1, 3, 9, 0, 0).unwrap(),
+    ));
+    let db = database::Database::open(":memory:", &time).unwrap();
+    fill(&db, &time);
+
+    c.bench_function("time_diff cold cache", |b| {
+        b.iter(|| {
+            db.clear_daily_summaries().unwrap();
+            business_logic::time_diff(&db).unwrap()
+        })
+    });
+    c.bench_function("time_diff warm cache", |b| {
+        b.iter(|| business_logic::time_diff(&db).unwrap())
+    });
+}
+
+criterion_group!(benches, balance);
+criterion_main!(benches);
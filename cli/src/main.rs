@@ -0,0 +1,640 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use timetrax::{auth, business_logic, compliance, config, database, import};
+
+use config::{Config, Overrides};
+use database::Database;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Track working times from the command line
+#[derive(Parser)]
+#[command(name = "timetrax", version)]
+struct Cli {
+    /// Configuration file
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Database file
+    #[arg(long, global = true, alias = "db")]
+    database: Option<PathBuf>,
+    /// Work with the data of this user instead of the default one
+    #[arg(long, global = true)]
+    user: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Start working on an item, ending the current one
+    Start { item: String },
+    /// Start a break
+    Pause,
+    /// End the workday
+    Stop,
+    /// Show current work, today's work and the balance
+    Status,
+    /// Set note and tags of the running interval, replacing the previous ones
+    Annotate {
+        note: Option<String>,
+        /// May be given several times
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Manage work items
+    #[command(subcommand)]
+    Items(ItemsCommand),
+    /// Show the current balance
+    Balance,
+    /// Show worked and expected time per day
+    Report {
+        /// First day of the report
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last day of the report
+        #[arg(long)]
+        to: NaiveDate,
+    },
+    /// List violations of the working time law (breaks, maximum work and rest)
+    Check {
+        /// First day to check
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last day to check
+        #[arg(long)]
+        to: NaiveDate,
+    },
+    /// Deduct breaks required by the working time law but not taken from the work time
+    DeductBreaks {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Show or set the time zone in which days start and end, e.g. when travelling. Days
+    /// recorded before keep their zone.
+    Timezone {
+        /// IANA name like Europe/Berlin
+        zone: Option<String>,
+        /// Follow the zone of the system again
+        #[arg(long, conflicts_with = "zone")]
+        system: bool,
+    },
+    /// Show or set the region whose public holidays need no work. Expected times are
+    /// recomputed from today on.
+    Region {
+        /// German state like Bayern or BadenWuerttemberg
+        name: Option<String>,
+        /// Do not take any holidays into account
+        #[arg(long, conflicts_with = "name")]
+        none: bool,
+    },
+    /// Edit past work times
+    #[command(subcommand)]
+    Edit(EditCommand),
+    /// Manage the user accounts of the server
+    #[command(subcommand)]
+    Users(UsersCommand),
+    /// Import the export of another time tracker
+    Import {
+        /// One of toggl, clockify (CSV detailed reports) or timewarrior (`timew export`)
+        #[arg(long)]
+        format: import::Format,
+        file: PathBuf,
+        /// Only report what would be imported and the conflicts
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommand {
+    /// Add a user. The password is read from standard input.
+    Add {
+        name: String,
+        /// Allow the user to manage other users
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Set the password of a user, read from standard input
+    Passwd { name: String },
+    /// Delete a user with all their data
+    Delete { name: String },
+    /// List all users
+    List,
+}
+
+#[derive(Subcommand)]
+enum ItemsCommand {
+    /// Add a work item
+    Add {
+        name: String,
+        /// Add it below this item
+        #[arg(long)]
+        parent: Option<String>,
+    },
+    /// Move a work item below another one, or to the top if no parent is given
+    Move {
+        name: String,
+        parent: Option<String>,
+    },
+    /// Hide a work item from the selection
+    Hide { name: String },
+    /// Offer a hidden work item in the selection again
+    Unhide { name: String },
+    /// Archive a finished work item. Its past work still shows up in reports.
+    Archive { name: String },
+    /// Restore an archived work item
+    Unarchive { name: String },
+    /// Rename a work item
+    Rename { name: String, new_name: String },
+    /// Set the description of a work item, or remove it if none is given
+    Describe {
+        name: String,
+        description: Option<String>,
+    },
+    /// List the available work items
+    List {
+        /// Include hidden and archived items
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum EditCommand {
+    /// Show the work times of a day
+    Show { date: NaiveDate },
+    /// Switch to an item (or to a break without item) at a time
+    Insert {
+        date: NaiveDate,
+        time: String,
+        item: Option<String>,
+    },
+    /// Move the switch at a time to another time of the same day
+    Move {
+        date: NaiveDate,
+        from: String,
+        to: String,
+    },
+    /// Change the item (or to a break without item) of the interval starting at a time
+    SetItem {
+        date: NaiveDate,
+        time: String,
+        item: Option<String>,
+    },
+    /// Turn the interval starting at a time into a break
+    Delete { date: NaiveDate, time: String },
+    /// Set note and tags of the interval starting at a time, replacing the previous ones
+    Annotate {
+        date: NaiveDate,
+        time: String,
+        note: Option<String>,
+        /// May be given several times
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Add the missing end of a day
+    Repair {
+        date: NaiveDate,
+        /// End the day at this time
+        #[arg(long, conflicts_with_all = ["shutdown", "expected"])]
+        at: Option<String>,
+        /// End the day at the last recorded shutdown
+        #[arg(long, conflicts_with = "expected")]
+        shutdown: bool,
+        /// End the day when the expected work is done
+        #[arg(long)]
+        expected: bool,
+    },
+    /// List days without end of workday
+    Inconsistent,
+}
+
+fn format_duration(duration: &Duration) -> String {
+    let sign = if *duration < Duration::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let hours = duration.num_hours().abs();
+    let minutes = duration.num_minutes().abs() - 60 * hours;
+    let seconds = duration.num_seconds().abs() - 60 * (minutes + 60 * hours);
+    format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds)
+}
+
+/// Time of a day in the zone of the user
+fn parse_time(db: &Database<Utc>, date: NaiveDate, s: &str) -> Result<DateTime<Utc>> {
+    let time = NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .map_err(|_| format!("Invalid time '{}'. Use HH:MM or HH:MM:SS", s))?;
+    Ok(db
+        .timezone()?
+        .from_local_datetime(&date.and_time(time))
+        .earliest()
+        .ok_or_else(|| format!("Time '{}' does not exist on {}", s, date))?
+        .with_timezone(&Utc))
+}
+
+fn find_item(db: &Database<Utc>, name: &str) -> Result<u64> {
+    db.get_available_work()?
+        .into_iter()
+        .find(|(item, _)| item == name)
+        .map(|(_, id)| id)
+        .ok_or_else(|| format!("Unknown work item '{}'", name).into())
+}
+
+/// Find an item by its path, including hidden and archived ones
+fn find_any_item(db: &Database<Utc>, name: &str) -> Result<u64> {
+    db.get_work_item_names()?
+        .into_iter()
+        .find(|(_, path)| path == name)
+        .map(|(id, _)| id)
+        .ok_or_else(|| format!("Unknown work item '{}'", name).into())
+}
+
+fn find_optional_item(db: &Database<Utc>, name: Option<&str>) -> Result<Option<u64>> {
+    name.map(|name| find_item(db, name)).transpose()
+}
+
+fn item_name(db: &Database<Utc>, id: Option<u64>) -> Result<String> {
+    Ok(match id {
+        None => "break".to_owned(),
+        Some(id) => db
+            .get_work_item_names()?
+            .remove(&id)
+            .unwrap_or_else(|| format!("#{}", id)),
+    })
+}
+
+fn print_inconsistent(days: &[NaiveDate]) {
+    if !days.is_empty() {
+        eprintln!(
+            "Warning: {} day(s) without end of workday are not counted: {}",
+            days.len(),
+            days.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+fn balance(db: &Database<Utc>) -> Result<Duration> {
+    let balance = business_logic::current_balance(db)?;
+    print_inconsistent(&balance.inconsistent_days);
+    Ok(balance.diff)
+}
+
+fn annotate(
+    db: &Database<Utc>,
+    start: DateTime<Utc>,
+    note: Option<String>,
+    tags: Vec<String>,
+) -> Result<()> {
+    db.with_savepoint(true, || {
+        db.set_interval_note(start, note.as_deref().filter(|note| !note.is_empty()))?;
+        db.set_interval_tags(start, &tags)
+    })?;
+    Ok(())
+}
+
+fn switch_work(db: &Database<Utc>, work_item: Option<u64>) -> Result<()> {
+    if db.get_current_work()? != work_item {
+        db.set_current_work(work_item)?;
+    }
+    Ok(())
+}
+
+fn items(db: &Database<Utc>, command: ItemsCommand) -> Result<()> {
+    match command {
+        ItemsCommand::Add { name, parent } => {
+            let parent = parent
+                .map(|parent| find_any_item(db, &parent))
+                .transpose()?;
+            if db.add_sub_item(parent, &name)? == 0 {
+                return Err(format!("Work item '{}' already exists", name).into());
+            }
+        }
+        ItemsCommand::Move { name, parent } => {
+            let parent = parent
+                .map(|parent| find_any_item(db, &parent))
+                .transpose()?;
+            db.set_work_item_parent(find_any_item(db, &name)?, parent)?;
+        }
+        ItemsCommand::Hide { name } => {
+            db.set_work_item_visible(find_any_item(db, &name)?, false)?;
+        }
+        ItemsCommand::Unhide { name } => {
+            db.set_work_item_visible(find_any_item(db, &name)?, true)?;
+        }
+        ItemsCommand::Archive { name } => {
+            db.set_work_item_archived(find_any_item(db, &name)?, true)?;
+        }
+        ItemsCommand::Unarchive { name } => {
+            db.set_work_item_archived(find_any_item(db, &name)?, false)?;
+        }
+        ItemsCommand::Rename { name, new_name } => {
+            db.rename_work_item(find_any_item(db, &name)?, &new_name)?;
+        }
+        ItemsCommand::Describe { name, description } => {
+            db.set_work_item_description(find_any_item(db, &name)?, description.as_deref())?;
+        }
+        ItemsCommand::List { all } => {
+            let mut paths = db.get_work_item_names()?;
+            let mut items: Vec<_> = db
+                .get_work_items()?
+                .into_iter()
+                .filter(|item| all || (item.visible && !item.archived))
+                .map(|item| (paths.remove(&item.id).unwrap_or_default(), item))
+                .collect();
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+            for (path, item) in items {
+                let mut line = path;
+                if item.archived {
+                    line.push_str(" (archived)");
+                } else if !item.visible {
+                    line.push_str(" (hidden)");
+                }
+                if let Some(description) = item.description {
+                    line = format!("{}: {}", line, description);
+                }
+                println!("{}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn report(db: &Database<Utc>, from: NaiveDate, to: NaiveDate) -> Result<()> {
+    let today = db.today()?;
+    let mut total = Duration::zero();
+    let mut date = from;
+    while date <= to {
+        let mut day = business_logic::get_workday_time(db, date)?;
+        if date == today {
+            day.work_done = Ok(business_logic::work_done_until_now(db, date)?);
+        }
+        let absence = day.absence.map(|a| a.kind.name()).unwrap_or_default();
+        match day.work_done {
+            Ok(work) => {
+                total = total + work - day.expected;
+                println!(
+                    "{} {} {:>10} {:>10} {:>10} {}",
+                    date,
+                    date.format("%a"),
+                    format_duration(&work),
+                    format_duration(&day.expected),
+                    format_duration(&(work - day.expected)),
+                    absence
+                );
+            }
+            Err(e) => println!(
+                "{} {} {:>10} {:>10} {:>10} {}",
+                date,
+                date.format("%a"),
+                "-",
+                format_duration(&day.expected),
+                "-",
+                e
+            ),
+        }
+        date += Duration::days(1);
+    }
+    println!("Total difference: {}", format_duration(&total));
+    Ok(())
+}
+
+fn edit(db: &Database<Utc>, command: EditCommand) -> Result<()> {
+    match command {
+        EditCommand::Show { date } => {
+            let mut annotations = db.get_annotations_on_date(&date)?;
+            for (work_item, start) in db.get_work_on_date(&date)? {
+                let mut line =
+                    format!("{} {}", start.format("%H:%M:%S"), item_name(db, work_item)?);
+                let annotation = annotations.remove(&start).unwrap_or_default();
+                if !annotation.tags.is_empty() {
+                    line += &format!(" [{}]", annotation.tags.join(", "));
+                }
+                if let Some(note) = annotation.note {
+                    line += &format!(": {}", note);
+                }
+                println!("{}", line);
+            }
+        }
+        EditCommand::Insert { date, time, item } => {
+            let work_item = find_optional_item(db, item.as_deref())?;
+            db.insert_work_switch(parse_time(db, date, &time)?, work_item)?;
+        }
+        EditCommand::Move { date, from, to } => {
+            db.move_work_switch(parse_time(db, date, &from)?, parse_time(db, date, &to)?)?;
+        }
+        EditCommand::SetItem { date, time, item } => {
+            let work_item = find_optional_item(db, item.as_deref())?;
+            db.set_interval_work(parse_time(db, date, &time)?, work_item)?;
+        }
+        EditCommand::Delete { date, time } => {
+            db.delete_interval(parse_time(db, date, &time)?)?;
+        }
+        EditCommand::Annotate {
+            date,
+            time,
+            note,
+            tags,
+        } => annotate(db, parse_time(db, date, &time)?, note, tags)?,
+        EditCommand::Repair {
+            date,
+            at,
+            shutdown,
+            expected,
+        } => {
+            let strategy = match (at, shutdown, expected) {
+                (Some(at), _, _) => business_logic::RepairStrategy::At(parse_time(db, date, &at)?),
+                (None, true, _) => business_logic::RepairStrategy::LastShutdown,
+                (None, false, true) => business_logic::RepairStrategy::ExpectedDuration,
+                (None, false, false) => {
+                    return Err("Choose one of --at, --shutdown or --expected".into())
+                }
+            };
+            business_logic::repair_day(db, date, &strategy)?;
+        }
+        EditCommand::Inconsistent => {
+            for date in business_logic::inconsistent_days(db)? {
+                println!("{}", date);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_password() -> Result<String> {
+    eprint!("Password: ");
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err("Password must not be empty".into());
+    }
+    Ok(password.to_owned())
+}
+
+fn users(db: &Database<Utc>, command: UsersCommand) -> Result<()> {
+    match command {
+        UsersCommand::Add { name, admin } => {
+            let hash = auth::hash_password(&read_password()?)?;
+            db.create_user(&name, Some(&hash), admin)?;
+        }
+        UsersCommand::Passwd { name } => {
+            let (user, _) = db.get_user_by_name(&name)?;
+            let hash = auth::hash_password(&read_password()?)?;
+            db.set_password_hash(user.id, &hash)?;
+        }
+        UsersCommand::Delete { name } => {
+            let (user, _) = db.get_user_by_name(&name)?;
+            if user.id == database::DEFAULT_USER {
+                return Err("The default user can't be deleted".into());
+            }
+            db.delete_user(user.id)?;
+        }
+        UsersCommand::List => {
+            for user in db.get_users()? {
+                println!("{}{}", user.name, if user.admin { " (admin)" } else { "" });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn import(db: &Database<Utc>, format: import::Format, file: PathBuf, dry_run: bool) -> Result<()> {
+    let entries = import::parse(format, std::fs::File::open(file)?, db.timezone()?)?;
+    let report = import::import(db, entries, dry_run)?;
+    for conflict in &report.conflicts {
+        let entry = &conflict.entry;
+        eprintln!(
+            "Skipped {} from {}: {}",
+            entry.work_item,
+            db.local_time(&entry.start)?.format("%Y-%m-%d %H:%M:%S"),
+            conflict.reason
+        );
+    }
+    if !report.new_items.is_empty() {
+        println!("New work items: {}", report.new_items.join(", "));
+    }
+    println!(
+        "{} {} entries, {} conflicts",
+        if dry_run { "Would import" } else { "Imported" },
+        report.imported,
+        report.conflicts.len()
+    );
+    Ok(())
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let config = Config::load(Overrides {
+        config_file: cli.config,
+        database: cli.database,
+        ..Default::default()
+    })?;
+    if let Some(legacy) = config.prepare_database()? {
+        eprintln!(
+            "Moved {} to {}",
+            legacy.display(),
+            config.database.display()
+        );
+    }
+    let mut db = Database::open(&config.database, &chrono::Utc)?;
+    if let Some(name) = cli.user {
+        let (user, _) = db.get_user_by_name(&name)?;
+        db.set_user(user.id);
+    }
+    match cli.command {
+        Command::Start { item } => {
+            let work_item = find_item(&db, &item)?;
+            switch_work(&db, Some(work_item))?;
+        }
+        Command::Pause => switch_work(&db, None)?,
+        Command::Stop => {
+            switch_work(&db, None)?;
+            let today = db.today()?;
+            println!(
+                "Worked today: {}",
+                format_duration(&business_logic::work_done_until_now(&db, today)?)
+            );
+        }
+        Command::Status => {
+            let today = db.today()?;
+            let current = db.get_current_work()?;
+            if current.is_some() {
+                println!("Working on: {}", item_name(&db, current)?);
+            } else {
+                println!("Not working");
+            }
+            println!(
+                "Worked today: {}",
+                format_duration(&business_logic::work_done_until_now(&db, today)?)
+            );
+            println!("Balance: {}", format_duration(&balance(&db)?));
+        }
+        Command::Annotate { note, tags } => {
+            let start = db
+                .get_current_interval()?
+                .ok_or("Not working, there is no interval to annotate")?;
+            annotate(&db, start, note, tags)?;
+        }
+        Command::Items(command) => items(&db, command)?,
+        Command::Balance => println!("{}", format_duration(&balance(&db)?)),
+        Command::Report { from, to } => report(&db, from, to)?,
+        Command::Check { from, to } => {
+            for violation in compliance::check_range(&db, from, to + Duration::days(1))? {
+                println!("{}", violation);
+            }
+        }
+        Command::DeductBreaks { enabled } => compliance::set_deduct_breaks(&db, enabled)?,
+        Command::Timezone { zone, system } => {
+            if system {
+                db.set_timezone(None)?;
+            } else if let Some(zone) = zone {
+                db.set_timezone(Some(zone.parse::<chrono_tz::Tz>()?))?;
+            }
+            let configured = db.get_configured_timezone()?.is_some();
+            println!(
+                "{}{}",
+                db.timezone()?.name(),
+                if configured { "" } else { " (system)" }
+            );
+        }
+        Command::Region { name, none } => {
+            if none {
+                db.set_holiday_region(None)?;
+            } else if let Some(name) = name {
+                let region = database::holiday_region_from_name(&name).ok_or_else(|| {
+                    let names: Vec<_> = database::HOLIDAY_REGIONS
+                        .iter()
+                        .map(database::holiday_region_name)
+                        .collect();
+                    format!("Unknown region {}, known are {}", name, names.join(", "))
+                })?;
+                db.set_holiday_region(Some(&region))?;
+            }
+            match db.get_holiday_region()? {
+                Some(region) => println!("{}", database::holiday_region_name(&region)),
+                None => println!("No holidays"),
+            }
+        }
+        Command::Edit(command) => edit(&db, command)?,
+        Command::Users(command) => users(&db, command)?,
+        Command::Import {
+            format,
+            file,
+            dry_run,
+        } => import(&db, format, file, dry_run)?,
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run(Cli::parse()) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
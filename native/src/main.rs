@@ -1,27 +1,53 @@
 #![windows_subsystem = "windows"]
-use chrono::Duration;
+use chrono::{Datelike, Duration, NaiveDate};
 use iced::executor;
-use iced::widget::{button, container, radio, text, text_input, Column, Row};
+use iced::widget::{
+    button, container, pick_list, radio, scrollable, text, text_input, Column, Row,
+};
 use iced::{Application, Command, Element, Length, Settings, Subscription, Theme};
-use rusqlite::OptionalExtension;
 
-use timetrax::{business_logic, database};
+use timetrax::{business_logic, compliance, config, database, export, idle, report};
 
 use database::Database;
 
 pub fn main() -> iced::Result {
+    let config = config::Overrides::from_args(std::env::args().skip(1))
+        .and_then(config::Config::load)
+        .and_then(|config| {
+            if let Some(legacy) = config.prepare_database()? {
+                println!(
+                    "Moved {} to {}",
+                    legacy.display(),
+                    config.database.display()
+                );
+            }
+            Ok(config)
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
     let settings = Settings {
         window: iced::window::Settings {
-            size: (300, 500),
-            resizable: false,
+            size: (700, 600),
+            resizable: true,
             decorations: true,
             ..Default::default()
         },
-        ..Default::default()
+        ..Settings::with_flags(config)
     };
     Timetrax::run(settings)
 }
 
+/// Centered and scrollable if the window is too small
+fn panel<'a>(content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    container(scrollable(content))
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .center_x()
+        .into()
+}
+
 struct Timetrax {
     now: chrono::DateTime<chrono::Local>,
     db: Database<'static, chrono::Utc>,
@@ -29,7 +55,102 @@ struct Timetrax {
     available_work: Vec<(String, u64)>,
     work_times: std::collections::HashMap<u64, Duration>,
     new_work_item: String,
+    /// Balance before today, recomputed when the day changes or past days were edited
     net_time: Duration,
+    net_time_date: NaiveDate,
+    inconsistent_days: Vec<NaiveDate>,
+    /// Working time law violations of today so far
+    violations: Vec<compliance::Violation>,
+    leave_at: Option<business_logic::LeaveAt>,
+    editor: Option<DayEditor>,
+    breakdown: Option<Breakdown>,
+    item_manager: Option<ItemManager>,
+    annotation: Option<CurrentAnnotation>,
+    export_dir: std::path::PathBuf,
+    idle_source: Option<std::sync::Arc<dyn idle::IdleSource + Send + Sync>>,
+    idle_tracker: idle::IdleTracker,
+    /// Idle period waiting for the user to decide what to do with it
+    idle_period: Option<idle::IdlePeriod>,
+    /// Why detecting or resolving an idle period failed
+    idle_error: Option<String>,
+}
+
+/// Note and tags of the running interval as edited, saved on submit
+struct CurrentAnnotation {
+    start: chrono::DateTime<chrono::Utc>,
+    note: String,
+    /// Comma separated
+    tags: String,
+    error: Option<String>,
+}
+
+/// Entry in the work selection of the day editor
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WorkChoice {
+    id: Option<u64>,
+    name: String,
+}
+
+impl std::fmt::Display for WorkChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+struct EditorEntry {
+    start: chrono::DateTime<chrono_tz::Tz>,
+    time: String,
+    work: WorkChoice,
+}
+
+struct ItemRow {
+    item: database::WorkItem,
+    /// Edited name and description, saved on submit
+    name: String,
+    description: String,
+    parent: WorkChoice,
+}
+
+/// Settings screen to rename, describe, move, hide and archive work items
+struct ItemManager {
+    items: Vec<ItemRow>,
+    /// Possible parents, the top level and all items by path
+    parents: Vec<WorkChoice>,
+    error: Option<String>,
+}
+
+/// Entry in the tag filter of the breakdown
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TagChoice(Option<String>);
+
+impl std::fmt::Display for TagChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0.as_deref().unwrap_or("All tags"))
+    }
+}
+
+/// Work per item in a week (by day) or a month (by week)
+struct Breakdown {
+    /// First day of the week or month
+    start: NaiveDate,
+    month: bool,
+    /// Only intervals with this tag count
+    tag: TagChoice,
+    tags: Vec<TagChoice>,
+    groups: Vec<(NaiveDate, Vec<(String, Duration)>)>,
+    total: Vec<(String, Duration)>,
+    error: Option<String>,
+}
+
+struct DayEditor {
+    date: NaiveDate,
+    entries: Vec<EditorEntry>,
+    new_time: String,
+    new_work: Option<WorkChoice>,
+    error: Option<String>,
+    violations: Vec<compliance::Violation>,
+    /// Result of the last export
+    export: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +159,49 @@ enum Message {
     ChangeWork(Option<u64>),
     TypeNewItem(String),
     AddNewWork,
+    ToggleEditor,
+    EditorDay(i64),
+    EditorTime(usize, String),
+    EditorMove(usize),
+    EditorWork(usize, WorkChoice),
+    EditorDelete(usize),
+    EditorNewTime(String),
+    EditorNewWork(WorkChoice),
+    EditorInsert,
+    EditorRepair(business_logic::RepairStrategy),
+    EditorExport,
+    EditorTimesheet,
+    ToggleBreakdown,
+    BreakdownPeriod(bool),
+    BreakdownMove(i32),
+    BreakdownTag(TagChoice),
+    ToggleItemManager,
+    ItemName(usize, String),
+    ItemDescription(usize, String),
+    ItemSave(usize),
+    ItemVisible(usize, bool),
+    ItemArchived(usize, bool),
+    ItemParent(usize, WorkChoice),
+    IdlePoll,
+    IdleTime(Option<Duration>),
+    IdleResolve(idle::Resolution),
+    IdleReassign(WorkChoice),
+    AnnotationNote(String),
+    AnnotationTags(String),
+    SaveAnnotation,
+}
+
+/// Measuring the idle time runs other programs, so it is done less often than the tick
+const IDLE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[cfg(target_os = "linux")]
+fn idle_source() -> Option<std::sync::Arc<dyn idle::IdleSource + Send + Sync>> {
+    Some(std::sync::Arc::new(idle::LinuxIdle))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn idle_source() -> Option<std::sync::Arc<dyn idle::IdleSource + Send + Sync>> {
+    None
 }
 
 fn format_duration(duration: &Duration) -> String {
@@ -52,38 +216,535 @@ fn format_duration(duration: &Duration) -> String {
     format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds)
 }
 
+/// Parse a time of day in `zone` as entered in the day editor
+fn parse_time(
+    zone: chrono_tz::Tz,
+    date: NaiveDate,
+    s: &str,
+) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let time = chrono::NaiveTime::parse_from_str(s.trim(), "%H:%M:%S")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(s.trim(), "%H:%M"))
+        .map_err(|_| format!("Invalid time '{}'. Use HH:MM or HH:MM:SS", s))?;
+    chrono::TimeZone::from_local_datetime(&zone, &date.and_time(time))
+        .earliest()
+        .map(|t| t.with_timezone(&chrono::Utc))
+        .ok_or_else(|| format!("Time '{}' does not exist on {}", s, date))
+}
+
+impl Timetrax {
+    fn work_choices(&self) -> Vec<WorkChoice> {
+        let mut choices = vec![WorkChoice {
+            id: None,
+            name: "Pause".to_owned(),
+        }];
+        choices.extend(self.available_work.iter().map(|(name, id)| WorkChoice {
+            id: Some(*id),
+            name: name.clone(),
+        }));
+        choices
+    }
+
+    fn work_choice(&self, id: Option<u64>) -> WorkChoice {
+        self.work_choices()
+            .into_iter()
+            .find(|choice| choice.id == id)
+            .unwrap_or_else(|| {
+                // Hidden and archived items keep their name
+                let id_value = id.unwrap_or_default();
+                let name = self
+                    .db
+                    .get_work_item_names()
+                    .ok()
+                    .and_then(|mut names| names.remove(&id_value))
+                    .unwrap_or_else(|| format!("#{}", id_value));
+                WorkChoice { id, name }
+            })
+    }
+
+    fn load_item_manager(&self) -> ItemManager {
+        let items = self.db.get_work_items().and_then(|items| {
+            let names = self.db.get_work_item_names()?;
+            Ok((items, names))
+        });
+        match items {
+            Ok((items, names)) => {
+                let mut parents: Vec<WorkChoice> = names
+                    .into_iter()
+                    .map(|(id, name)| WorkChoice { id: Some(id), name })
+                    .collect();
+                parents.sort_by(|a, b| a.name.cmp(&b.name));
+                parents.insert(
+                    0,
+                    WorkChoice {
+                        id: None,
+                        name: "(top level)".to_owned(),
+                    },
+                );
+                let mut items: Vec<ItemRow> = items
+                    .into_iter()
+                    .map(|item| ItemRow {
+                        name: item.name.clone(),
+                        description: item.description.clone().unwrap_or_default(),
+                        parent: parents
+                            .iter()
+                            .find(|parent| parent.id == item.parent)
+                            .cloned()
+                            .unwrap_or_else(|| parents[0].clone()),
+                        item,
+                    })
+                    .collect();
+                // Show the tree, every item below its parent
+                let position = |id| parents.iter().position(|p| p.id == Some(id));
+                items.sort_by_key(|row| position(row.item.id));
+                ItemManager {
+                    items,
+                    parents,
+                    error: None,
+                }
+            }
+            Err(e) => ItemManager {
+                items: Vec::new(),
+                parents: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    /// Change the work item in row `i` of the item manager and reload on success
+    fn edit_item<F>(&mut self, i: usize, edit: F)
+    where
+        F: FnOnce(&Database<'static, chrono::Utc>, &ItemRow) -> database::Result<()>,
+    {
+        let Some(row) = self.item_manager.as_ref().and_then(|m| m.items.get(i)) else {
+            return;
+        };
+        match edit(&self.db, row) {
+            Ok(()) => {
+                self.item_manager = Some(self.load_item_manager());
+                if let Ok(work) = self.db.get_available_work() {
+                    self.available_work = work;
+                }
+            }
+            Err(e) => {
+                if let Some(manager) = &mut self.item_manager {
+                    manager.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    fn view_item_manager<'a>(&self, manager: &'a ItemManager) -> Element<'a, Message> {
+        let mut col = Column::new().spacing(5);
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(text("Work items").width(Length::Fixed(150.0)))
+                .push(button(text("Done")).on_press(Message::ToggleItemManager)),
+        );
+        for (i, row) in manager.items.iter().enumerate() {
+            let item = &row.item;
+            // An item can't be moved below itself
+            let parents: Vec<WorkChoice> = manager
+                .parents
+                .iter()
+                .filter(|parent| parent.id != Some(item.id))
+                .cloned()
+                .collect();
+            col = col.push(
+                Row::new()
+                    .spacing(5)
+                    .push(
+                        text_input("name", &row.name)
+                            .on_input(move |s| Message::ItemName(i, s))
+                            .on_submit(Message::ItemSave(i))
+                            .width(Length::Fixed(120.0)),
+                    )
+                    .push(
+                        text_input("description", &row.description)
+                            .on_input(move |s| Message::ItemDescription(i, s))
+                            .on_submit(Message::ItemSave(i))
+                            .width(Length::Fixed(120.0)),
+                    )
+                    .push(pick_list(parents, Some(row.parent.clone()), move |p| {
+                        Message::ItemParent(i, p)
+                    }))
+                    .push(
+                        button(text(if item.visible { "Hide" } else { "Show" }))
+                            .on_press(Message::ItemVisible(i, !item.visible)),
+                    )
+                    .push(
+                        button(text(if item.archived { "Restore" } else { "Archive" }))
+                            .on_press(Message::ItemArchived(i, !item.archived)),
+                    ),
+            );
+        }
+        if let Some(error) = &manager.error {
+            col = col.push(text(error));
+        }
+        col.into()
+    }
+
+    fn load_editor(&self, date: NaiveDate) -> DayEditor {
+        let (entries, error) = match self.db.get_work_on_date(&date) {
+            Ok(entries) => (
+                entries
+                    .into_iter()
+                    .map(|(work_item, start)| EditorEntry {
+                        start,
+                        time: start.format("%H:%M:%S").to_string(),
+                        work: self.work_choice(work_item),
+                    })
+                    .collect(),
+                None,
+            ),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        DayEditor {
+            date,
+            entries,
+            new_time: String::new(),
+            new_work: None,
+            error,
+            violations: compliance::check_day(&self.db, date).unwrap_or_default(),
+            export: None,
+        }
+    }
+
+    /// Run an edit on the currently shown day and reload it on success
+    fn edit_day<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&Database<'static, chrono::Utc>, &DayEditor) -> Result<(), String>,
+    {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        let date = editor.date;
+        match edit(&self.db, editor) {
+            Ok(()) => {
+                self.editor = Some(self.load_editor(date));
+                self.refresh();
+            }
+            Err(e) => {
+                if let Some(editor) = &mut self.editor {
+                    editor.error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn load_annotation(&self) -> Option<CurrentAnnotation> {
+        let start = self.db.get_current_interval().ok()??;
+        let local = self.db.local_time(&start).ok()?;
+        let annotation = self
+            .db
+            .get_annotations_on_date(&local.date_naive())
+            .ok()?
+            .remove(&local)
+            .unwrap_or_default();
+        Some(CurrentAnnotation {
+            start,
+            note: annotation.note.unwrap_or_default(),
+            tags: annotation.tags.join(", "),
+            error: None,
+        })
+    }
+
+    /// Today in the zone of the user, which may differ from the one of the system
+    fn today(&self) -> NaiveDate {
+        self.db.today().unwrap_or_else(|_| self.now.date_naive())
+    }
+
+    fn update_work_times(&mut self) {
+        if let Ok(times) = business_logic::work_item_durations(&self.db, self.today()) {
+            self.work_times = times;
+        }
+        if let Ok(violations) = compliance::check_day(&self.db, self.today()) {
+            self.violations = violations;
+        }
+        self.leave_at = business_logic::leave_at_with_balance(&self.db, self.net_time).ok();
+    }
+
+    fn load_breakdown(&self, start: NaiveDate, month: bool, tag: TagChoice) -> Breakdown {
+        let (end, grouping) = if month {
+            (
+                start + chrono::Months::new(1),
+                business_logic::Grouping::Week,
+            )
+        } else {
+            (start + Duration::days(7), business_logic::Grouping::Day)
+        };
+        let mut breakdown = Breakdown {
+            start,
+            month,
+            tag,
+            tags: Vec::new(),
+            groups: Vec::new(),
+            total: Vec::new(),
+            error: None,
+        };
+        let (names, tags) = match self
+            .db
+            .get_work_item_names()
+            .and_then(|names| Ok((names, self.db.get_tags()?)))
+        {
+            Ok(names) => names,
+            Err(e) => {
+                breakdown.error = Some(e.to_string());
+                return breakdown;
+            }
+        };
+        breakdown.tags = std::iter::once(TagChoice(None))
+            .chain(tags.into_iter().map(|tag| TagChoice(Some(tag))))
+            .collect();
+        let tag = breakdown.tag.0.as_deref();
+        let named = |totals: business_logic::ItemTotals| {
+            let mut named: Vec<(String, Duration)> = totals
+                .into_iter()
+                .map(|(id, duration)| {
+                    let name = names.get(&id).cloned();
+                    (name.unwrap_or_else(|| format!("#{}", id)), duration)
+                })
+                .collect();
+            named.sort();
+            named
+        };
+        // Projects include the time of their tasks
+        let roll_up = |groups: Vec<(NaiveDate, business_logic::ItemTotals)>| {
+            groups
+                .into_iter()
+                .map(|(date, totals)| {
+                    Ok((date, business_logic::roll_up_totals(&self.db, &totals)?))
+                })
+                .collect::<Result<Vec<_>, business_logic::Error>>()
+        };
+        let totals = business_logic::work_item_totals(&self.db, start, end, grouping, tag)
+            .and_then(roll_up)
+            .and_then(|groups| {
+                let total = business_logic::work_item_totals(
+                    &self.db,
+                    start,
+                    end,
+                    business_logic::Grouping::Range,
+                    tag,
+                )
+                .and_then(roll_up)?;
+                Ok((groups, total))
+            });
+        match totals {
+            Ok((groups, total)) => {
+                breakdown.groups = groups
+                    .into_iter()
+                    .map(|(date, totals)| (date, named(totals)))
+                    .collect();
+                breakdown.total = total
+                    .into_iter()
+                    .flat_map(|(_, totals)| named(totals))
+                    .collect();
+            }
+            Err(e) => breakdown.error = Some(e.to_string()),
+        }
+        breakdown
+    }
+
+    fn view_breakdown<'a>(&self, breakdown: &'a Breakdown) -> Element<'a, Message> {
+        let title = if breakdown.month {
+            breakdown.start.format("%B %Y").to_string()
+        } else {
+            format!("Week of {}", breakdown.start)
+        };
+        let mut col = Column::new().spacing(5);
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(button(text("<")).on_press(Message::BreakdownMove(-1)))
+                .push(text(title).width(Length::Fixed(150.0)))
+                .push(button(text(">")).on_press(Message::BreakdownMove(1)))
+                .push(button(text("Done")).on_press(Message::ToggleBreakdown)),
+        );
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(radio(
+                    "Week",
+                    false,
+                    Some(breakdown.month),
+                    Message::BreakdownPeriod,
+                ))
+                .push(radio(
+                    "Month",
+                    true,
+                    Some(breakdown.month),
+                    Message::BreakdownPeriod,
+                ))
+                .push(pick_list(
+                    breakdown.tags.clone(),
+                    Some(breakdown.tag.clone()),
+                    Message::BreakdownTag,
+                )),
+        );
+        let item_rows = |mut col: Column<'a, Message>, items: &'a [(String, Duration)]| {
+            for (name, duration) in items {
+                col = col.push(
+                    Row::new()
+                        .push(text(name).width(Length::Fixed(150.0)))
+                        .push(text(format_duration(duration))),
+                );
+            }
+            col
+        };
+        for (date, items) in &breakdown.groups {
+            let label = if breakdown.month {
+                format!("Week of {}", date)
+            } else {
+                date.format("%a %Y-%m-%d").to_string()
+            };
+            col = item_rows(col.push(text(label)), items);
+        }
+        col = item_rows(col.push(text("Total")), &breakdown.total);
+        if let Some(error) = &breakdown.error {
+            col = col.push(text(error));
+        }
+        col.into()
+    }
+
+    /// Reload everything that may have changed by editing past work times
+    fn refresh(&mut self) {
+        match business_logic::balance_before_today(&self.db) {
+            Ok(balance) => {
+                self.net_time = balance.diff;
+                self.net_time_date = self.today();
+                self.inconsistent_days = balance.inconsistent_days;
+            }
+            Err(e) => println!("{}", e),
+        }
+        if let Ok(current_work) = self.db.get_current_work() {
+            self.current_work = current_work;
+        }
+        self.annotation = self.load_annotation();
+        self.update_work_times();
+    }
+
+    fn view_editor<'a>(&self, editor: &'a DayEditor) -> Element<'a, Message> {
+        let choices = self.work_choices();
+        let mut col = Column::new().spacing(5);
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(button(text("<")).on_press(Message::EditorDay(-1)))
+                .push(text(editor.date.format("%a %Y-%m-%d")).width(Length::Fixed(150.0)))
+                .push(button(text(">")).on_press(Message::EditorDay(1)))
+                .push(button(text("Done")).on_press(Message::ToggleEditor)),
+        );
+        for (i, entry) in editor.entries.iter().enumerate() {
+            col = col.push(
+                Row::new()
+                    .spacing(5)
+                    .push(
+                        text_input("HH:MM:SS", &entry.time)
+                            .on_input(move |s| Message::EditorTime(i, s))
+                            .on_submit(Message::EditorMove(i))
+                            .width(Length::Fixed(90.0)),
+                    )
+                    .push(
+                        pick_list(choices.clone(), Some(entry.work.clone()), move |w| {
+                            Message::EditorWork(i, w)
+                        })
+                        .width(Length::Fixed(150.0)),
+                    )
+                    .push(button(text("x")).on_press(Message::EditorDelete(i))),
+            );
+        }
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(
+                    text_input("HH:MM:SS", &editor.new_time)
+                        .on_input(Message::EditorNewTime)
+                        .on_submit(Message::EditorInsert)
+                        .width(Length::Fixed(90.0)),
+                )
+                .push(
+                    pick_list(choices, editor.new_work.clone(), Message::EditorNewWork)
+                        .width(Length::Fixed(150.0)),
+                )
+                .push(button(text("+")).on_press(Message::EditorInsert)),
+        );
+        if self.inconsistent_days.contains(&editor.date) {
+            col = col.push(text("No end of workday. Close the day:"));
+            col = col.push(
+                Row::new()
+                    .spacing(5)
+                    .push(button(text("At shutdown")).on_press(Message::EditorRepair(
+                        business_logic::RepairStrategy::LastShutdown,
+                    )))
+                    .push(
+                        button(text("After expected time")).on_press(Message::EditorRepair(
+                            business_logic::RepairStrategy::ExpectedDuration,
+                        )),
+                    ),
+            );
+        }
+        for violation in &editor.violations {
+            col = col.push(text(format!("Warning: {}", violation)));
+        }
+        if let Some(error) = &editor.error {
+            col = col.push(text(error));
+        }
+        col = col.push(
+            Row::new()
+                .spacing(10)
+                .push(button(text("Export month as CSV")).on_press(Message::EditorExport))
+                .push(button(text("Timesheet")).on_press(Message::EditorTimesheet)),
+        );
+        if let Some(export) = &editor.export {
+            col = col.push(text(export));
+        }
+        col.into()
+    }
+}
+
 impl Application for Timetrax {
     type Executor = executor::Default;
     type Message = Message;
     type Theme = Theme;
-    type Flags = ();
+    type Flags = config::Config;
 
-    fn new(_flags: ()) -> (Self, Command<Message>) {
+    fn new(config: config::Config) -> (Self, Command<Message>) {
         let now = chrono::Local::now();
-        let db = Database::open("work.db", &chrono::Utc).unwrap();
+        let db = Database::open(&config.database, &chrono::Utc).unwrap();
         //business_logic::fix_missing_expected(&db).unwrap();
-        let account_start = db
-            .get_kv::<i64>("account_start")
-            .optional()
-            .unwrap()
-            .map(Duration::seconds)
-            .unwrap_or_else(Duration::zero);
-        let net_time = account_start + business_logic::time_diff(&db).unwrap()
-            - business_logic::get_expected_work_or_insert_default(&db, now.date_naive()).unwrap();
+        let business_logic::TimeDiff {
+            diff: net_time,
+            inconsistent_days,
+        } = business_logic::balance_before_today(&db).unwrap();
+        let net_time_date = db.today().unwrap();
         let available_work = db.get_available_work().unwrap();
         let current_work = db.get_current_work().unwrap();
-        (
-            Timetrax {
-                now,
-                db,
-                current_work,
-                available_work,
-                work_times: Default::default(),
-                new_work_item: Default::default(),
-                net_time,
-            },
-            Command::none(),
-        )
+        let mut timetrax = Timetrax {
+            now,
+            db,
+            current_work,
+            available_work,
+            work_times: Default::default(),
+            new_work_item: Default::default(),
+            net_time,
+            net_time_date,
+            inconsistent_days,
+            violations: Vec::new(),
+            leave_at: None,
+            editor: None,
+            breakdown: None,
+            item_manager: None,
+            annotation: None,
+            export_dir: config.export_dir,
+            // Turned off with 0 minutes
+            idle_source: idle_source().filter(|_| config.idle_minutes > 0),
+            idle_tracker: idle::IdleTracker::new(Duration::minutes(config.idle_minutes.into())),
+            idle_period: None,
+            idle_error: None,
+        };
+        timetrax.annotation = timetrax.load_annotation();
+        (timetrax, Command::none())
     }
 
     fn title(&self) -> String {
@@ -97,19 +758,31 @@ impl Application for Timetrax {
 
                 if now != self.now {
                     self.now = now;
-                    if let Ok(mut work_time) = self.db.get_work_on_date(&now.date_naive()) {
-                        work_time.push((None, now));
-                        let mut times = std::collections::HashMap::new();
-                        for work in work_time.windows(2) {
-                            let start = &work[0];
-                            let end = &work[1];
-                            if let Some(work_item) = start.0 {
-                                let duration = end.1 - start.1;
-                                let worked = times.entry(work_item).or_insert_with(Duration::zero);
-                                *worked = *worked + duration;
-                            }
+                    if self.today() != self.net_time_date {
+                        self.refresh();
+                    } else {
+                        self.update_work_times();
+                    }
+                }
+            }
+            Message::IdlePoll => {
+                if let (Some(source), None) = (&self.idle_source, &self.idle_period) {
+                    // Off the UI thread, as it waits for other programs
+                    let source = source.clone();
+                    return Command::perform(async move { source.idle_time() }, Message::IdleTime);
+                }
+            }
+            Message::IdleTime(idle_time) => {
+                if self.idle_period.is_none() {
+                    match self
+                        .idle_tracker
+                        .poll(&idle::MeasuredIdle(idle_time), &self.db)
+                    {
+                        Ok(period) => {
+                            self.idle_period = period;
+                            self.idle_error = None;
                         }
-                        self.work_times = times;
+                        Err(e) => self.idle_error = Some(e.to_string()),
                     }
                 }
             }
@@ -119,6 +792,7 @@ impl Application for Timetrax {
                     match err {
                         Ok(_) => {
                             self.current_work = v;
+                            self.annotation = self.load_annotation();
                         }
                         Err(e) => {
                             println!("{:?}", e);
@@ -126,6 +800,51 @@ impl Application for Timetrax {
                     }
                 }
             }
+            Message::IdleResolve(resolution) => {
+                if let Some(period) = &self.idle_period {
+                    // The prompt stays open if the decision can't be applied
+                    match idle::resolve(&self.db, period, resolution) {
+                        Ok(()) => {
+                            self.idle_period = None;
+                            self.idle_error = None;
+                        }
+                        Err(e) => self.idle_error = Some(e.to_string()),
+                    }
+                    self.refresh();
+                }
+            }
+            Message::IdleReassign(work) => {
+                let resolution = match work.id {
+                    Some(id) => idle::Resolution::Reassign(id),
+                    None => idle::Resolution::Discard,
+                };
+                return self.update(Message::IdleResolve(resolution));
+            }
+            Message::AnnotationNote(note) => {
+                if let Some(annotation) = &mut self.annotation {
+                    annotation.note = note;
+                }
+            }
+            Message::AnnotationTags(tags) => {
+                if let Some(annotation) = &mut self.annotation {
+                    annotation.tags = tags;
+                }
+            }
+            Message::SaveAnnotation => {
+                if let Some(annotation) = &mut self.annotation {
+                    let note = Some(annotation.note.trim()).filter(|note| !note.is_empty());
+                    let tags: Vec<String> = annotation.tags.split(',').map(str::to_owned).collect();
+                    let saved = self.db.with_savepoint(true, || {
+                        self.db.set_interval_note(annotation.start, note)?;
+                        self.db.set_interval_tags(annotation.start, &tags)
+                    });
+                    // Keep the input for another try
+                    match saved {
+                        Ok(()) => self.annotation = self.load_annotation(),
+                        Err(e) => annotation.error = Some(e.to_string()),
+                    }
+                }
+            }
             Message::TypeNewItem(s) => {
                 self.new_work_item = s;
             }
@@ -137,22 +856,237 @@ impl Application for Timetrax {
                     self.available_work = work;
                 }
             }
+            Message::ToggleEditor => {
+                self.editor = match self.editor {
+                    Some(_) => None,
+                    None => Some(self.load_editor(self.today())),
+                };
+            }
+            Message::ToggleBreakdown => {
+                self.breakdown = match self.breakdown {
+                    Some(_) => None,
+                    None => {
+                        let today = self.today();
+                        let monday =
+                            today - Duration::days(today.weekday().num_days_from_monday().into());
+                        Some(self.load_breakdown(monday, false, TagChoice(None)))
+                    }
+                };
+            }
+            Message::ToggleItemManager => {
+                self.item_manager = match self.item_manager {
+                    Some(_) => None,
+                    None => Some(self.load_item_manager()),
+                };
+            }
+            Message::ItemName(i, name) => {
+                if let Some(row) = self.item_manager.as_mut().and_then(|m| m.items.get_mut(i)) {
+                    row.name = name;
+                }
+            }
+            Message::ItemDescription(i, description) => {
+                if let Some(row) = self.item_manager.as_mut().and_then(|m| m.items.get_mut(i)) {
+                    row.description = description;
+                }
+            }
+            Message::ItemSave(i) => self.edit_item(i, |db, row| {
+                if row.name.trim() != row.item.name && !row.name.trim().is_empty() {
+                    db.rename_work_item(row.item.id, row.name.trim())?;
+                }
+                let description = Some(row.description.trim()).filter(|d| !d.is_empty());
+                db.set_work_item_description(row.item.id, description)
+            }),
+            Message::ItemVisible(i, visible) => {
+                self.edit_item(i, |db, row| db.set_work_item_visible(row.item.id, visible))
+            }
+            Message::ItemArchived(i, archived) => self.edit_item(i, |db, row| {
+                db.set_work_item_archived(row.item.id, archived)
+            }),
+            Message::ItemParent(i, parent) => {
+                self.edit_item(i, |db, row| db.set_work_item_parent(row.item.id, parent.id))
+            }
+            Message::BreakdownPeriod(month) => {
+                if let Some(breakdown) = &self.breakdown {
+                    let date = breakdown.start;
+                    let start = if month {
+                        date.with_day(1).unwrap()
+                    } else {
+                        date - Duration::days(date.weekday().num_days_from_monday().into())
+                    };
+                    self.breakdown = Some(self.load_breakdown(start, month, breakdown.tag.clone()));
+                }
+            }
+            Message::BreakdownMove(offset) => {
+                if let Some(breakdown) = &self.breakdown {
+                    let start = match (breakdown.month, offset) {
+                        (true, 1) => breakdown.start + chrono::Months::new(1),
+                        (true, _) => breakdown.start - chrono::Months::new(1),
+                        (false, offset) => breakdown.start + Duration::days(7 * i64::from(offset)),
+                    };
+                    self.breakdown =
+                        Some(self.load_breakdown(start, breakdown.month, breakdown.tag.clone()));
+                }
+            }
+            Message::BreakdownTag(tag) => {
+                if let Some(breakdown) = &self.breakdown {
+                    self.breakdown =
+                        Some(self.load_breakdown(breakdown.start, breakdown.month, tag));
+                }
+            }
+            Message::EditorDay(offset) => {
+                if let Some(editor) = &self.editor {
+                    self.editor = Some(self.load_editor(editor.date + Duration::days(offset)));
+                }
+            }
+            Message::EditorTime(i, s) => {
+                if let Some(entry) = self.editor.as_mut().and_then(|e| e.entries.get_mut(i)) {
+                    entry.time = s;
+                }
+            }
+            Message::EditorMove(i) => self.edit_day(|db, editor| {
+                let entry = &editor.entries[i];
+                let zone = db.timezone().map_err(|e| e.to_string())?;
+                let to = parse_time(zone, editor.date, &entry.time)?;
+                db.move_work_switch(entry.start.with_timezone(&chrono::Utc), to)
+                    .map_err(|e| e.to_string())
+            }),
+            Message::EditorWork(i, work) => self.edit_day(|db, editor| {
+                let entry = &editor.entries[i];
+                db.set_interval_work(entry.start.with_timezone(&chrono::Utc), work.id)
+                    .map_err(|e| e.to_string())
+            }),
+            Message::EditorDelete(i) => self.edit_day(|db, editor| {
+                let entry = &editor.entries[i];
+                db.delete_interval(entry.start.with_timezone(&chrono::Utc))
+                    .map_err(|e| e.to_string())
+            }),
+            Message::EditorNewTime(s) => {
+                if let Some(editor) = &mut self.editor {
+                    editor.new_time = s;
+                }
+            }
+            Message::EditorNewWork(work) => {
+                if let Some(editor) = &mut self.editor {
+                    editor.new_work = Some(work);
+                }
+            }
+            Message::EditorRepair(strategy) => self.edit_day(|db, editor| {
+                business_logic::repair_day(db, editor.date, &strategy).map_err(|e| e.to_string())
+            }),
+            Message::EditorInsert => self.edit_day(|db, editor| {
+                let zone = db.timezone().map_err(|e| e.to_string())?;
+                let at = parse_time(zone, editor.date, &editor.new_time)?;
+                let work = editor.new_work.as_ref().and_then(|w| w.id);
+                db.insert_work_switch(at, work).map_err(|e| e.to_string())
+            }),
+            Message::EditorExport => {
+                if let Some(editor) = &mut self.editor {
+                    // The whole month of the shown day
+                    let from = editor.date.with_day(1).unwrap();
+                    let to = from
+                        .checked_add_months(chrono::Months::new(1))
+                        .and_then(|next| next.pred_opt())
+                        .unwrap();
+                    editor.export = Some(
+                        match export::export_to_dir(&self.db, from, to, &self.export_dir) {
+                            Ok((intervals, days)) => format!(
+                                "Exported to {} and {}",
+                                intervals.display(),
+                                days.display()
+                            ),
+                            Err(e) => e.to_string(),
+                        },
+                    );
+                }
+            }
+            Message::EditorTimesheet => {
+                if let Some(editor) = &mut self.editor {
+                    let format = report::Format::Html;
+                    let path = self.export_dir.join(format!(
+                        "timetrax-timesheet-{}.{}",
+                        editor.date.format("%Y-%m"),
+                        format.extension()
+                    ));
+                    let written = report::timesheet(&self.db, editor.date)
+                        .map_err(|e| e.to_string())
+                        .and_then(|timesheet| {
+                            std::fs::create_dir_all(&self.export_dir)
+                                .and_then(|_| {
+                                    std::fs::write(&path, report::render(&timesheet, format))
+                                })
+                                .map_err(|e| e.to_string())
+                        });
+                    editor.export = Some(match written {
+                        Ok(()) => format!("Timesheet written to {}", path.display()),
+                        Err(e) => e,
+                    });
+                }
+            }
         }
 
         Command::none()
     }
 
     fn view(&self) -> Element<Message> {
+        if let Some(manager) = &self.item_manager {
+            return panel(self.view_item_manager(manager));
+        }
+        if let Some(breakdown) = &self.breakdown {
+            return panel(self.view_breakdown(breakdown));
+        }
+        if let Some(editor) = &self.editor {
+            return panel(self.view_editor(editor));
+        }
         let col1_width = Length::Fixed(150.0);
         let mut col = Column::new();
+        if let Some(period) = &self.idle_period {
+            let zone = self.db.timezone().unwrap_or(chrono_tz::Tz::UTC);
+            let time = |t: chrono::DateTime<chrono::Utc>| {
+                t.with_timezone(&zone).format("%H:%M").to_string()
+            };
+            col = col.push(text(format!(
+                "Away from {} to {}. Count this time as",
+                time(period.start),
+                time(period.end)
+            )));
+            col = col.push(
+                Row::new()
+                    .spacing(5)
+                    .push(
+                        button(text("Work")).on_press(Message::IdleResolve(idle::Resolution::Keep)),
+                    )
+                    .push(
+                        button(text("Break"))
+                            .on_press(Message::IdleResolve(idle::Resolution::Discard)),
+                    )
+                    .push(pick_list(
+                        self.work_choices()
+                            .into_iter()
+                            .filter(|choice| {
+                                choice.id.is_some() && choice.id != Some(period.work_item)
+                            })
+                            .collect::<Vec<_>>(),
+                        None,
+                        Message::IdleReassign,
+                    )),
+            );
+        }
+        if let Some(error) = &self.idle_error {
+            col = col.push(text(error));
+        }
         let pause_button = radio("Pause", None, Some(self.current_work), Message::ChangeWork)
             .width(Length::Fixed(150.0));
         col = col.push(pause_button);
         let mut total_time = Duration::zero();
-        for (name, id) in &self.available_work {
+        let mut work: Vec<_> = self.available_work.iter().collect();
+        work.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, id) in work {
+            // Tasks are indented below their project
+            let depth = path.matches(database::PATH_SEPARATOR).count();
+            let name = path.rsplit(database::PATH_SEPARATOR).next().unwrap_or(path);
             let mut row = Row::new();
             let button = radio(
-                name,
+                format!("{}{}", "    ".repeat(depth), name),
                 Some(*id),
                 Some(self.current_work),
                 Message::ChangeWork,
@@ -165,6 +1099,28 @@ impl Application for Timetrax {
             }
             col = col.push(row);
         }
+        if let Some(annotation) = &self.annotation {
+            col = col.push(
+                Row::new()
+                    .spacing(5)
+                    .push(
+                        text_input("note", &annotation.note)
+                            .on_input(Message::AnnotationNote)
+                            .on_submit(Message::SaveAnnotation)
+                            .width(col1_width),
+                    )
+                    .push(
+                        text_input("tags", &annotation.tags)
+                            .on_input(Message::AnnotationTags)
+                            .on_submit(Message::SaveAnnotation)
+                            .width(Length::Fixed(100.0)),
+                    )
+                    .push(button(text("Save")).on_press(Message::SaveAnnotation)),
+            );
+            if let Some(error) = &annotation.error {
+                col = col.push(text(error));
+            }
+        }
         col = col.push(
             Row::new()
                 .push(
@@ -186,15 +1142,51 @@ impl Application for Timetrax {
                 .push(text("Total net time").width(col1_width))
                 .push(text(format_duration(&(self.net_time + total_time)))),
         );
-        container(col)
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .center_x()
-            .into()
+        if let Some(leave_at) = &self.leave_at {
+            let time = |t: Option<chrono::DateTime<chrono_tz::Tz>>| {
+                t.map(|t| t.format("%H:%M:%S").to_string())
+                    .unwrap_or_else(|| "now".to_owned())
+            };
+            col = col.push(
+                Row::new()
+                    .push(text("Leave at").width(col1_width))
+                    .push(text(time(leave_at.even_day))),
+            );
+            col = col.push(
+                Row::new()
+                    .push(text("Even balance at").width(col1_width))
+                    .push(text(time(leave_at.even_balance))),
+            );
+        }
+        if let Some(first) = self.inconsistent_days.first() {
+            col = col.push(text(format!(
+                "{} day(s) without end of workday, not counted. First: {}",
+                self.inconsistent_days.len(),
+                first
+            )));
+        }
+        for violation in &self.violations {
+            col = col.push(text(format!("Warning: {}", violation)));
+        }
+        col = col.push(
+            Row::new()
+                .spacing(5)
+                .push(button(text("Edit times")).on_press(Message::ToggleEditor))
+                .push(button(text("Totals")).on_press(Message::ToggleBreakdown))
+                .push(button(text("Items")).on_press(Message::ToggleItemManager)),
+        );
+        panel(col)
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        iced::time::every(std::time::Duration::from_millis(900))
-            .map(|_| Message::Tick(chrono::Local::now()))
+        let tick = iced::time::every(std::time::Duration::from_millis(900))
+            .map(|_| Message::Tick(chrono::Local::now()));
+        if self.idle_source.is_none() {
+            return tick;
+        }
+        Subscription::batch([
+            tick,
+            iced::time::every(IDLE_POLL_INTERVAL).map(|_| Message::IdlePoll),
+        ])
     }
 }
@@ -0,0 +1,350 @@
+"use strict";
+
+const state = {
+  items: [],
+  current: null,
+  today: null,
+  balance: null,
+  leaveAt: null,
+  // Zone in which the server counts the days of the user
+  timezone: null,
+  // Time of the last refresh, the running interval is advanced locally from here
+  loadedAt: Date.now(),
+};
+
+function $(id) {
+  return document.getElementById(id);
+}
+
+function deviceTimezone() {
+  return Intl.DateTimeFormat().resolvedOptions().timeZone;
+}
+
+// Date of a day in the zone of the user, which may differ from the one of this device
+function userDate(date) {
+  return new Intl.DateTimeFormat("en-CA", { timeZone: state.timezone || undefined }).format(date);
+}
+
+function localDate(date) {
+  const pad = (n) => String(n).padStart(2, "0");
+  return `${date.getFullYear()}-${pad(date.getMonth() + 1)}-${pad(date.getDate())}`;
+}
+
+function formatDuration(seconds) {
+  const sign = seconds < 0 ? "-" : "";
+  seconds = Math.abs(Math.trunc(seconds));
+  const pad = (n) => String(n).padStart(2, "0");
+  const hours = Math.floor(seconds / 3600);
+  const minutes = Math.floor(seconds / 60) % 60;
+  return `${sign}${pad(hours)}:${pad(minutes)}:${pad(seconds % 60)}`;
+}
+
+function formatTime(timestamp) {
+  return new Date(timestamp).toLocaleTimeString([], { hour12: false });
+}
+
+function showError(message) {
+  $("error").textContent = message;
+  $("error").hidden = !message;
+}
+
+async function api(method, path, body) {
+  const options = { method, headers: {} };
+  if (body !== undefined) {
+    options.headers["Content-Type"] = "application/json";
+    options.body = JSON.stringify(body);
+  }
+  const response = await fetch(`api/${path}`, options);
+  if (response.status === 401 && path !== "login") {
+    showLogin(true);
+  }
+  if (!response.ok) {
+    let message = response.statusText;
+    try {
+      message = (await response.json()).message;
+    } catch (e) {
+      // Not a JSON error body
+    }
+    throw new Error(message);
+  }
+  return response.status === 204 ? null : response.json();
+}
+
+// Run an action and refresh everything afterwards, showing errors instead of throwing
+async function perform(action) {
+  try {
+    await action();
+    showError("");
+  } catch (e) {
+    showError(e.message);
+  }
+  await refresh();
+}
+
+function showLogin(show) {
+  $("login").hidden = !show;
+  $("tracker").hidden = show || !$("editor").hidden;
+  if (show) {
+    $("editor").hidden = true;
+  }
+}
+
+function itemOptions(select, selected) {
+  select.replaceChildren();
+  const pause = new Option("Pause", "");
+  select.add(pause);
+  for (const item of state.items) {
+    select.add(new Option(item.path, item.id));
+  }
+  select.value = selected === null || selected === undefined ? "" : String(selected);
+}
+
+function selectedItem(select) {
+  return select.value === "" ? null : Number(select.value);
+}
+
+function renderTracker() {
+  const elapsed = state.current === null ? 0 : (Date.now() - state.loadedAt) / 1000;
+  const form = $("work");
+  form.replaceChildren();
+  const choices = [{ id: null, path: "Pause" }, ...state.items];
+  for (const item of choices) {
+    const label = document.createElement("label");
+    const radio = document.createElement("input");
+    radio.type = "radio";
+    radio.name = "work";
+    radio.checked = item.id === state.current;
+    radio.addEventListener("change", () =>
+      perform(() => api("PUT", "current_work", { work_item: item.id }))
+    );
+    const title = document.createElement("span");
+    title.append(radio, " ", item.path);
+    label.append(title);
+    const time = state.today && state.today.items.find((t) => t.work_item === item.id);
+    if (time) {
+      const duration = document.createElement("span");
+      duration.textContent = formatDuration(
+        time.seconds + (item.id === state.current ? elapsed : 0)
+      );
+      label.append(duration);
+    }
+    form.append(label);
+  }
+  if (state.today && state.today.work_done !== null) {
+    $("total").textContent = formatDuration(state.today.work_done + elapsed);
+  }
+  if (state.balance) {
+    const balance = state.balance.seconds + elapsed;
+    $("balance").textContent = formatDuration(balance);
+    $("balance").classList.toggle("negative", balance < 0);
+    const days = state.balance.inconsistent_days;
+    $("inconsistent").hidden = days.length === 0;
+    $("inconsistent").textContent = `${days.length} day(s) without end of workday, not counted. First: ${days[0]}`;
+  }
+  if (state.leaveAt) {
+    // While not working the forecast moves with the time
+    const delay = state.current === null ? Date.now() - state.loadedAt : 0;
+    const leave = (time) => (time === null ? "now" : formatTime(Date.parse(time) + delay));
+    $("leave-day").textContent = leave(state.leaveAt.even_day);
+    $("leave-balance").textContent = leave(state.leaveAt.even_balance);
+  }
+  const device = deviceTimezone();
+  $("timezone").hidden = !state.timezone || state.timezone === device;
+  $("timezone-text").textContent = `Days are counted in ${state.timezone}, this device is in ${device}.`;
+}
+
+async function renderEditor() {
+  const date = $("editor-date").value;
+  if ($("editor").hidden || !date) {
+    return;
+  }
+  const [year, month] = date.split("-");
+  $("timesheet").href = `api/timesheet/${year}/${Number(month)}`;
+  const day = await api("GET", `days/${date}`);
+  const rows = day.entries.map((entry) => {
+    const row = document.createElement("tr");
+    const time = document.createElement("input");
+    time.type = "time";
+    time.step = 1;
+    time.value = formatTime(entry.start);
+    time.addEventListener("change", () =>
+      perform(() =>
+        api("PUT", "switches", {
+          from: entry.start,
+          to: new Date(`${date}T${time.value}`).toISOString(),
+        })
+      )
+    );
+    const item = document.createElement("select");
+    itemOptions(item, entry.work_item);
+    item.addEventListener("change", () =>
+      perform(() =>
+        api("PUT", "intervals", { start: entry.start, work_item: selectedItem(item) })
+      )
+    );
+    const note = document.createElement("input");
+    note.placeholder = "note";
+    note.value = entry.note || "";
+    const tags = document.createElement("input");
+    tags.placeholder = "tags";
+    tags.value = entry.tags.join(", ");
+    const annotate = () =>
+      perform(() =>
+        api("PUT", "intervals/annotation", {
+          start: entry.start,
+          note: note.value.trim(),
+          tags: tags.value.split(",").map((tag) => tag.trim()).filter((tag) => tag),
+        })
+      );
+    note.addEventListener("change", annotate);
+    tags.addEventListener("change", annotate);
+    const remove = document.createElement("button");
+    remove.type = "button";
+    remove.textContent = "Delete";
+    remove.addEventListener("click", () =>
+      perform(() => api("DELETE", `intervals?start=${encodeURIComponent(entry.start)}`))
+    );
+    const cells = [time, item, note, tags, remove].map((element) => {
+      const cell = document.createElement("td");
+      cell.append(element);
+      return cell;
+    });
+    row.append(...cells);
+    return row;
+  });
+  $("entries").replaceChildren(...rows);
+  const insertItem = $("insert-item");
+  const selected = insertItem.options.length ? selectedItem(insertItem) : null;
+  itemOptions(insertItem, selected);
+  $("repair").hidden = day.work_done !== null;
+}
+
+// Downloads use the session cookie, so plain links suffice
+function updateExportLinks() {
+  const range = `from=${$("export-from").value}&to=${$("export-to").value}`;
+  $("export-intervals").href = `api/export/intervals.csv?${range}`;
+  $("export-days").href = `api/export/days.csv?${range}`;
+}
+
+let events = null;
+
+// Changes made by other clients
+function connectEvents() {
+  if (events) {
+    events.close();
+  }
+  events = new EventSource("api/events");
+  events.addEventListener("message", () => refresh());
+}
+
+async function refresh() {
+  try {
+    const timezone = await api("GET", "timezone");
+    state.timezone = timezone.timezone;
+    const today = userDate(new Date());
+    const [items, current, day, balance, leaveAt] = await Promise.all([
+      api("GET", "work_items"),
+      api("GET", "current_work"),
+      api("GET", `days/${today}`),
+      api("GET", "balance"),
+      api("GET", "leave_at"),
+    ]);
+    showLogin(false);
+    Object.assign(state, {
+      items,
+      current: current.work_item,
+      today: day,
+      balance,
+      leaveAt,
+      loadedAt: Date.now(),
+    });
+    renderTracker();
+    await renderEditor();
+  } catch (e) {
+    showError(e.message);
+  }
+}
+
+function setup() {
+  $("login").addEventListener("submit", async (event) => {
+    event.preventDefault();
+    try {
+      await api("POST", "login", {
+        name: $("login-name").value,
+        password: $("login-password").value,
+      });
+      $("login-password").value = "";
+      showError("");
+      connectEvents();
+      await refresh();
+    } catch (e) {
+      showError(e.message);
+    }
+  });
+  $("logout").addEventListener("click", async () => {
+    await api("POST", "logout").catch(() => {});
+    showLogin(true);
+  });
+  $("add-item").addEventListener("submit", (event) => {
+    event.preventDefault();
+    const title = $("new-item").value.trim();
+    if (title) {
+      $("new-item").value = "";
+      perform(() => api("POST", "work_items", { title }));
+    }
+  });
+  $("toggle-editor").addEventListener("click", () => {
+    $("tracker").hidden = true;
+    $("editor").hidden = false;
+    $("editor-date").value = $("editor-date").value || localDate(new Date());
+    if (!$("export-from").value) {
+      // Default to the current month
+      const now = new Date();
+      $("export-from").value = localDate(new Date(now.getFullYear(), now.getMonth(), 1));
+      $("export-to").value = localDate(new Date(now.getFullYear(), now.getMonth() + 1, 0));
+      updateExportLinks();
+    }
+    renderEditor().catch((e) => showError(e.message));
+  });
+  $("close-editor").addEventListener("click", () => {
+    $("editor").hidden = true;
+    $("tracker").hidden = false;
+  });
+  $("use-timezone").addEventListener("click", () =>
+    perform(() => api("PUT", "timezone", { timezone: deviceTimezone() }))
+  );
+  $("export-from").addEventListener("change", updateExportLinks);
+  $("export-to").addEventListener("change", updateExportLinks);
+  $("editor-date").addEventListener("change", () =>
+    renderEditor().catch((e) => showError(e.message))
+  );
+  $("insert").addEventListener("click", () => {
+    const date = $("editor-date").value;
+    const time = $("insert-time").value;
+    if (!time) {
+      showError("Enter a time to insert");
+      return;
+    }
+    perform(() =>
+      api("POST", "switches", {
+        at: new Date(`${date}T${time}`).toISOString(),
+        work_item: selectedItem($("insert-item")),
+      })
+    );
+  });
+  for (const button of $("repair").querySelectorAll("button")) {
+    button.addEventListener("click", () =>
+      perform(() =>
+        api("POST", `days/${$("editor-date").value}/repair`, {
+          strategy: button.dataset.strategy,
+        })
+      )
+    );
+  }
+  connectEvents();
+  refresh();
+  setInterval(renderTracker, 1000);
+  setInterval(refresh, 60000);
+}
+
+setup();
@@ -0,0 +1,781 @@
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use timetrax::{business_logic, database, export, report};
+
+use crate::auth::{self, CurrentUser};
+use crate::error::ApiError;
+use crate::events::{self, Event};
+use crate::AppState;
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+pub fn services(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_work_items)
+        .service(add_work_item)
+        .service(update_work_item)
+        .service(get_current_work)
+        .service(set_current_work)
+        .service(get_intervals)
+        .service(get_expected_time)
+        .service(set_expected_time)
+        .service(get_balance)
+        .service(get_leave_at)
+        .service(get_timezone)
+        .service(set_timezone)
+        .service(get_holiday_region)
+        .service(set_holiday_region)
+        .service(get_day)
+        .service(insert_switch)
+        .service(move_switch)
+        .service(set_interval_work)
+        .service(delete_interval)
+        .service(annotate_interval)
+        .service(get_tags)
+        .service(repair_day)
+        .service(export_intervals)
+        .service(export_days)
+        .service(get_timesheet)
+        .service(get_work_item_totals)
+        .service(events::events)
+        .configure(auth::services);
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct WorkItem {
+    id: u64,
+    parent: Option<u64>,
+    title: String,
+    /// Titles of the parents and the item, like `Project / Task`
+    path: String,
+    description: Option<String>,
+    visible: bool,
+    archived: bool,
+}
+
+impl WorkItem {
+    fn new(item: database::WorkItem, paths: &HashMap<u64, String>) -> Self {
+        Self {
+            id: item.id,
+            parent: item.parent,
+            path: paths.get(&item.id).cloned().unwrap_or_default(),
+            title: item.name,
+            description: item.description,
+            visible: item.visible,
+            archived: item.archived,
+        }
+    }
+}
+
+/// Tell `Some(None)` for an explicit null apart from a missing field
+fn explicit_null<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize)]
+struct WorkItemFilter {
+    /// Include hidden and archived items
+    #[serde(default)]
+    all: bool,
+}
+
+#[actix_web::get("/work_items")]
+async fn get_work_items(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    filter: web::Query<WorkItemFilter>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let paths = db.get_work_item_names()?;
+    let items: Vec<WorkItem> = db
+        .get_work_items()?
+        .into_iter()
+        .filter(|item| filter.all || (item.visible && !item.archived))
+        .map(|item| WorkItem::new(item, &paths))
+        .collect();
+    Ok(web::Json(items))
+}
+
+#[derive(Deserialize)]
+struct NewWorkItem {
+    title: String,
+    parent: Option<u64>,
+}
+
+#[actix_web::post("/work_items")]
+async fn add_work_item(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    item: web::Json<NewWorkItem>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    if item.title.trim().is_empty() {
+        return Err(ApiError::BadRequest("Title must not be empty".to_owned()));
+    }
+    if db.add_sub_item(item.parent, &item.title)? == 0 {
+        return Err(ApiError::Conflict(format!(
+            "A work item named '{}' already exists there",
+            item.title
+        )));
+    }
+    let added = db
+        .get_work_items()?
+        .into_iter()
+        .find(|added| added.parent == item.parent && added.name == item.title)
+        .ok_or_else(|| ApiError::Conflict("Work item was not added".to_owned()))?;
+    data.notify(&user.0, Event::WorkItems);
+    Ok(HttpResponse::Created().json(WorkItem::new(added, &db.get_work_item_names()?)))
+}
+
+#[derive(Deserialize)]
+struct WorkItemUpdate {
+    title: Option<String>,
+    /// An empty description removes it
+    description: Option<String>,
+    visible: Option<bool>,
+    archived: Option<bool>,
+    /// Null moves the item to the top
+    #[serde(default, deserialize_with = "explicit_null")]
+    parent: Option<Option<u64>>,
+}
+
+#[actix_web::put("/work_items/{id}")]
+async fn update_work_item(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    id: web::Path<u64>,
+    update: web::Json<WorkItemUpdate>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let id = id.into_inner();
+    // All or nothing, a rejected parent must not leave the item renamed
+    db.with_savepoint(true, || {
+        if let Some(title) = &update.title {
+            if title.trim().is_empty() {
+                return Err(ApiError::BadRequest("Title must not be empty".to_owned()));
+            }
+            db.rename_work_item(id, title)?;
+        }
+        if let Some(description) = &update.description {
+            let description = Some(description.trim()).filter(|d| !d.is_empty());
+            db.set_work_item_description(id, description)?;
+        }
+        if let Some(visible) = update.visible {
+            db.set_work_item_visible(id, visible)?;
+        }
+        if let Some(archived) = update.archived {
+            db.set_work_item_archived(id, archived)?;
+        }
+        if let Some(parent) = update.parent {
+            db.set_work_item_parent(id, parent)?;
+        }
+        Ok(())
+    })?;
+    data.notify(&user.0, Event::WorkItems);
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Serialize, Deserialize)]
+struct CurrentWork {
+    work_item: Option<u64>,
+}
+
+#[actix_web::get("/current_work")]
+async fn get_current_work(data: web::Data<AppState>, user: CurrentUser) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    Ok(web::Json(CurrentWork {
+        work_item: db.get_current_work()?,
+    }))
+}
+
+#[actix_web::put("/current_work")]
+async fn set_current_work(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    work: web::Json<CurrentWork>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    if db.get_current_work()? != work.work_item {
+        db.set_current_work(work.work_item)?;
+        data.notify(
+            &user.0,
+            Event::CurrentWork {
+                work_item: work.work_item,
+            },
+        );
+    }
+    Ok(web::Json(work.into_inner()))
+}
+
+#[derive(Deserialize)]
+struct DateRange {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+impl DateRange {
+    fn check(&self) -> Result<()> {
+        if self.to < self.from || self.to - self.from > Duration::days(366) {
+            return Err(ApiError::BadRequest(
+                "Date range has to be ascending and at most a year".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Interval {
+    work_item: u64,
+    start: DateTime<Tz>,
+    /// None if the interval is still running or the day has no end
+    end: Option<DateTime<Tz>>,
+}
+
+/// Intervals of all days from `from` to `to`, both inclusive
+#[actix_web::get("/intervals")]
+async fn get_intervals(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    range: web::Query<DateRange>,
+) -> Result<impl Responder> {
+    range.check()?;
+    let db = data.db(&user.0);
+    let mut intervals = Vec::new();
+    let mut date = range.from;
+    while date <= range.to {
+        let times = db.get_work_on_date(&date)?;
+        for (i, (work_item, start)) in times.iter().enumerate() {
+            if let Some(work_item) = work_item {
+                intervals.push(Interval {
+                    work_item: *work_item,
+                    start: *start,
+                    end: times.get(i + 1).map(|next| next.1),
+                });
+            }
+        }
+        date += Duration::days(1);
+    }
+    Ok(web::Json(intervals))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExpectedTime {
+    seconds: i64,
+}
+
+#[actix_web::get("/expected_time/{date}")]
+async fn get_expected_time(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    date: web::Path<NaiveDate>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let expected = business_logic::get_expected_work_or_insert_default(&db, *date)?;
+    Ok(web::Json(ExpectedTime {
+        seconds: expected.num_seconds(),
+    }))
+}
+
+#[actix_web::put("/expected_time/{date}")]
+async fn set_expected_time(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    date: web::Path<NaiveDate>,
+    expected: web::Json<ExpectedTime>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    if expected.seconds < 0 {
+        return Err(ApiError::BadRequest(
+            "Expected time must not be negative".to_owned(),
+        ));
+    }
+    db.set_expected_time(*date, expected.seconds)?;
+    data.notify(&user.0, Event::ExpectedTime { date: *date });
+    Ok(web::Json(expected.into_inner()))
+}
+
+#[derive(Serialize)]
+struct Balance {
+    seconds: i64,
+    inconsistent_days: Vec<NaiveDate>,
+}
+
+#[actix_web::get("/balance")]
+async fn get_balance(data: web::Data<AppState>, user: CurrentUser) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let balance = business_logic::current_balance(&db)?;
+    Ok(web::Json(Balance {
+        seconds: balance.diff.num_seconds(),
+        inconsistent_days: balance.inconsistent_days,
+    }))
+}
+
+#[derive(Serialize)]
+struct LeaveAt {
+    /// None once today's expected work is done
+    even_day: Option<DateTime<Tz>>,
+    /// None once the balance is not negative
+    even_balance: Option<DateTime<Tz>>,
+}
+
+/// Earliest end of today's work for an even day and an even balance
+#[actix_web::get("/leave_at")]
+async fn get_leave_at(data: web::Data<AppState>, user: CurrentUser) -> Result<impl Responder> {
+    let leave_at = business_logic::leave_at(&data.db(&user.0))?;
+    Ok(web::Json(LeaveAt {
+        even_day: leave_at.even_day,
+        even_balance: leave_at.even_balance,
+    }))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Timezone {
+    /// IANA name like `Europe/Berlin`. When setting, null follows the zone of the server.
+    timezone: Option<String>,
+    /// Whether the user chose the zone
+    #[serde(default)]
+    configured: bool,
+}
+
+/// Zone in which the days of the user start and end
+#[actix_web::get("/timezone")]
+async fn get_timezone(data: web::Data<AppState>, user: CurrentUser) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    Ok(web::Json(Timezone {
+        timezone: Some(db.timezone()?.name().to_owned()),
+        configured: db.get_configured_timezone()?.is_some(),
+    }))
+}
+
+/// Change the zone, e.g. after travelling. Days recorded before keep their zone.
+#[actix_web::put("/timezone")]
+async fn set_timezone(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    timezone: web::Json<Timezone>,
+) -> Result<impl Responder> {
+    let zone = timezone
+        .timezone
+        .as_deref()
+        .map(|name| name.parse::<Tz>().map_err(ApiError::BadRequest))
+        .transpose()?;
+    data.db(&user.0).set_timezone(zone)?;
+    data.notify(&user.0, Event::Timezone);
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Serialize, Deserialize)]
+struct HolidayRegion {
+    /// German state like `Bayern`, null if holidays are not taken into account
+    region: Option<String>,
+}
+
+/// Region whose public holidays need no work
+#[actix_web::get("/holiday_region")]
+async fn get_holiday_region(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+) -> Result<impl Responder> {
+    let region = data.db(&user.0).get_holiday_region()?;
+    Ok(web::Json(HolidayRegion {
+        region: region.map(|region| database::holiday_region_name(&region).to_owned()),
+    }))
+}
+
+/// Change the region. Expected times are recomputed from today on.
+#[actix_web::put("/holiday_region")]
+async fn set_holiday_region(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    region: web::Json<HolidayRegion>,
+) -> Result<impl Responder> {
+    let region = region
+        .region
+        .as_deref()
+        .map(|name| {
+            database::holiday_region_from_name(name)
+                .ok_or_else(|| ApiError::BadRequest(format!("Unknown region {}", name)))
+        })
+        .transpose()?;
+    data.db(&user.0).set_holiday_region(region.as_ref())?;
+    data.notify(&user.0, Event::HolidayRegion);
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Serialize)]
+struct Entry {
+    start: DateTime<Tz>,
+    /// None for a break or the end of the day
+    work_item: Option<u64>,
+    note: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ItemTime {
+    work_item: u64,
+    seconds: i64,
+}
+
+/// Sorted by work item for stable output
+fn item_times(totals: business_logic::ItemTotals) -> Vec<ItemTime> {
+    let mut items: Vec<ItemTime> = totals
+        .into_iter()
+        .map(|(work_item, duration)| ItemTime {
+            work_item,
+            seconds: duration.num_seconds(),
+        })
+        .collect();
+    items.sort_by_key(|item| item.work_item);
+    items
+}
+
+#[derive(Serialize)]
+struct Day {
+    entries: Vec<Entry>,
+    items: Vec<ItemTime>,
+    /// None if the day has no end of workday
+    work_done: Option<i64>,
+    expected: i64,
+}
+
+/// Work times of a day. On the current day a running interval counts until now.
+#[actix_web::get("/days/{date}")]
+async fn get_day(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    date: web::Path<NaiveDate>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let date = date.into_inner();
+    let times = db.get_work_on_date(&date)?;
+    let mut annotations = db.get_annotations_on_date(&date)?;
+    let items = item_times(business_logic::work_item_durations(&db, date)?);
+    let work_done = match business_logic::work_done_until_now(&db, date) {
+        Ok(work_done) => Some(work_done.num_seconds()),
+        Err(business_logic::Error::Inconsistent(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+    let expected = business_logic::get_expected_work_with_absence(&db, date)?;
+    Ok(web::Json(Day {
+        entries: times
+            .into_iter()
+            .map(|(work_item, start)| {
+                let annotation = annotations.remove(&start).unwrap_or_default();
+                Entry {
+                    start,
+                    work_item,
+                    note: annotation.note,
+                    tags: annotation.tags,
+                }
+            })
+            .collect(),
+        items,
+        work_done,
+        expected: expected.num_seconds(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct NewSwitch {
+    at: DateTime<Utc>,
+    work_item: Option<u64>,
+}
+
+#[actix_web::post("/switches")]
+async fn insert_switch(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    switch: web::Json<NewSwitch>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    db.insert_work_switch(switch.at, switch.work_item)?;
+    data.notify(
+        &user.0,
+        Event::Times {
+            date: db.local_date(&switch.at)?,
+        },
+    );
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Deserialize)]
+struct MovedSwitch {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+}
+
+#[actix_web::put("/switches")]
+async fn move_switch(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    switch: web::Json<MovedSwitch>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    db.move_work_switch(switch.from, switch.to)?;
+    data.notify(
+        &user.0,
+        Event::Times {
+            date: db.local_date(&switch.from)?,
+        },
+    );
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Deserialize)]
+struct IntervalWork {
+    start: DateTime<Utc>,
+    work_item: Option<u64>,
+}
+
+#[actix_web::put("/intervals")]
+async fn set_interval_work(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    interval: web::Json<IntervalWork>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    db.set_interval_work(interval.start, interval.work_item)?;
+    data.notify(
+        &user.0,
+        Event::Times {
+            date: db.local_date(&interval.start)?,
+        },
+    );
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Deserialize)]
+struct IntervalStart {
+    start: DateTime<Utc>,
+}
+
+/// Turn the interval into a break
+#[actix_web::delete("/intervals")]
+async fn delete_interval(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    interval: web::Query<IntervalStart>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    db.delete_interval(interval.start)?;
+    data.notify(
+        &user.0,
+        Event::Times {
+            date: db.local_date(&interval.start)?,
+        },
+    );
+    Ok(HttpResponse::NoContent())
+}
+
+#[derive(Deserialize)]
+struct IntervalAnnotation {
+    start: DateTime<Utc>,
+    /// Empty or missing removes the note
+    note: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Replace note and tags of an interval
+#[actix_web::put("/intervals/annotation")]
+async fn annotate_interval(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    annotation: web::Json<IntervalAnnotation>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let start = annotation.start;
+    let note = annotation.note.as_deref().filter(|note| !note.is_empty());
+    db.with_savepoint(true, || {
+        db.set_interval_note(start, note)?;
+        db.set_interval_tags(start, &annotation.tags)
+    })?;
+    data.notify(
+        &user.0,
+        Event::Times {
+            date: db.local_date(&annotation.start)?,
+        },
+    );
+    Ok(HttpResponse::NoContent())
+}
+
+/// Names of all tags used so far
+#[actix_web::get("/tags")]
+async fn get_tags(data: web::Data<AppState>, user: CurrentUser) -> Result<impl Responder> {
+    Ok(web::Json(data.db(&user.0).get_tags()?))
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+enum Repair {
+    LastShutdown,
+    ExpectedDuration,
+    At { at: DateTime<Utc> },
+}
+
+#[actix_web::post("/days/{date}/repair")]
+async fn repair_day(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    date: web::Path<NaiveDate>,
+    repair: web::Json<Repair>,
+) -> Result<impl Responder> {
+    let db = data.db(&user.0);
+    let strategy = match repair.into_inner() {
+        Repair::LastShutdown => business_logic::RepairStrategy::LastShutdown,
+        Repair::ExpectedDuration => business_logic::RepairStrategy::ExpectedDuration,
+        Repair::At { at } => business_logic::RepairStrategy::At(at),
+    };
+    business_logic::repair_day(&db, *date, &strategy)?;
+    data.notify(&user.0, Event::Times { date: *date });
+    Ok(HttpResponse::NoContent())
+}
+
+fn csv_download(name: &str, range: &DateRange, content: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            actix_web::http::header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"timetrax-{}-{}-{}.csv\"",
+                name, range.from, range.to
+            ),
+        ))
+        .body(content)
+}
+
+/// CSV of the intervals from `from` to `to`, both inclusive
+#[actix_web::get("/export/intervals.csv")]
+async fn export_intervals(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    range: web::Query<DateRange>,
+) -> Result<impl Responder> {
+    range.check()?;
+    let mut content = Vec::new();
+    export::write_intervals(&data.db(&user.0), range.from, range.to, &mut content)?;
+    Ok(csv_download("intervals", &range, content))
+}
+
+/// CSV of the daily summaries from `from` to `to`, both inclusive
+#[actix_web::get("/export/days.csv")]
+async fn export_days(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    range: web::Query<DateRange>,
+) -> Result<impl Responder> {
+    range.check()?;
+    let mut content = Vec::new();
+    export::write_days(&data.db(&user.0), range.from, range.to, &mut content)?;
+    Ok(csv_download("days", &range, content))
+}
+
+#[derive(Deserialize)]
+struct TimesheetFormat {
+    /// txt, md, html or csv, HTML by default
+    format: Option<String>,
+}
+
+/// Timesheet of a month for HR
+#[actix_web::get("/timesheet/{year}/{month}")]
+async fn get_timesheet(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    path: web::Path<(i32, u32)>,
+    query: web::Query<TimesheetFormat>,
+) -> Result<impl Responder> {
+    let (year, month) = path.into_inner();
+    let date = NaiveDate::from_ymd_opt(year, month, 1)
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid month {}-{}", year, month)))?;
+    let format: report::Format = query
+        .format
+        .as_deref()
+        .unwrap_or("html")
+        .parse()
+        .map_err(ApiError::BadRequest)?;
+    let timesheet = report::timesheet(&data.db(&user.0), date)?;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .body(report::render(&timesheet, format)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Grouping {
+    Range,
+    Day,
+    Week,
+}
+
+#[derive(Deserialize)]
+struct TotalsQuery {
+    from: NaiveDate,
+    to: NaiveDate,
+    group: Option<Grouping>,
+    /// Add the time of sub-items to their parents
+    #[serde(default)]
+    roll_up: bool,
+    /// Only count intervals with this tag
+    tag: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ItemTotals {
+    /// First day of the group
+    start: NaiveDate,
+    items: Vec<ItemTime>,
+}
+
+/// Work per item from `from` to `to`, both inclusive, in one group or grouped by day or week,
+/// optionally only of intervals with a tag
+#[actix_web::get("/work_item_totals")]
+async fn get_work_item_totals(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    query: web::Query<TotalsQuery>,
+) -> Result<impl Responder> {
+    let TotalsQuery {
+        from,
+        to,
+        group,
+        roll_up,
+        tag,
+    } = query.into_inner();
+    DateRange { from, to }.check()?;
+    let grouping = match group {
+        None | Some(Grouping::Range) => business_logic::Grouping::Range,
+        Some(Grouping::Day) => business_logic::Grouping::Day,
+        Some(Grouping::Week) => business_logic::Grouping::Week,
+    };
+    let db = data.db(&user.0);
+    let totals = business_logic::work_item_totals(
+        &db,
+        from,
+        to + Duration::days(1),
+        grouping,
+        tag.as_deref(),
+    )?;
+    let groups = totals
+        .into_iter()
+        .map(|(start, items)| {
+            let items = if roll_up {
+                business_logic::roll_up_totals(&db, &items)?
+            } else {
+                items
+            };
+            Ok(ItemTotals {
+                start,
+                items: item_times(items),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(web::Json(groups))
+}
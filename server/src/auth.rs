@@ -0,0 +1,249 @@
+//! Logins and user accounts. Clients authenticate with the session cookie set by the login
+//! or with the returned token as `Authorization: Bearer <token>`.
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest, HttpResponse, Responder};
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use std::future::{ready, Ready};
+use timetrax::auth;
+use timetrax::database::{User, DEFAULT_USER};
+
+use crate::error::ApiError;
+use crate::AppState;
+
+type Result<T> = std::result::Result<T, ApiError>;
+
+const SESSION_COOKIE: &str = "timetrax_session";
+const SESSION_DAYS: i64 = 30;
+
+pub fn services(cfg: &mut web::ServiceConfig) {
+    cfg.service(login)
+        .service(logout)
+        .service(get_me)
+        .service(set_password)
+        .service(get_users)
+        .service(add_user)
+        .service(delete_user);
+}
+
+/// The logged in user of a request
+pub struct CurrentUser(pub User);
+
+/// A logged in user with the admin role
+pub struct Admin(pub User);
+
+fn session_token(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_owned);
+    bearer.or_else(|| {
+        req.cookie(SESSION_COOKIE)
+            .map(|cookie| cookie.value().to_owned())
+    })
+}
+
+fn authenticate(req: &HttpRequest) -> Result<CurrentUser> {
+    let data = req
+        .app_data::<web::Data<AppState>>()
+        .expect("AppState is registered");
+    let token =
+        session_token(req).ok_or_else(|| ApiError::Unauthorized("Not logged in".to_owned()))?;
+    let db = data.lock();
+    let valid_since = db.now() - Duration::days(SESSION_DAYS);
+    db.get_session_user(&auth::token_hash(&token), valid_since)?
+        .map(CurrentUser)
+        .ok_or_else(|| ApiError::Unauthorized("Session is invalid or expired".to_owned()))
+}
+
+impl FromRequest for CurrentUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req))
+    }
+}
+
+impl FromRequest for Admin {
+    type Error = ApiError;
+    type Future = Ready<Result<Self>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(authenticate(req).and_then(|CurrentUser(user)| {
+            if user.admin {
+                Ok(Admin(user))
+            } else {
+                Err(ApiError::Forbidden("Only admins can do this".to_owned()))
+            }
+        }))
+    }
+}
+
+#[derive(Serialize)]
+struct UserInfo {
+    id: i64,
+    name: String,
+    admin: bool,
+}
+
+impl From<User> for UserInfo {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            name: user.name,
+            admin: user.admin,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    name: String,
+    password: String,
+}
+
+#[derive(Serialize)]
+struct Session {
+    token: String,
+    user: UserInfo,
+}
+
+fn hash_password(password: &str) -> Result<String> {
+    if password.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Password must not be empty".to_owned(),
+        ));
+    }
+    auth::hash_password(password).map_err(|e| ApiError::BadRequest(e.to_string()))
+}
+
+#[actix_web::post("/login")]
+async fn login(
+    data: web::Data<AppState>,
+    credentials: web::Json<Credentials>,
+) -> Result<impl Responder> {
+    let failed = || ApiError::Unauthorized("Wrong user name or password".to_owned());
+    // Don't hold the database while verifying, that is slow on purpose
+    let (user, hash) = data
+        .lock()
+        .get_user_by_name(&credentials.name)
+        .map_err(|_| failed())?;
+    match hash {
+        Some(hash) if auth::verify_password(&credentials.password, &hash) => {}
+        _ => return Err(failed()),
+    }
+    let token = auth::new_session_token();
+    data.lock()
+        .create_session(&auth::token_hash(&token), user.id)?;
+    let cookie = Cookie::build(SESSION_COOKIE, token.clone())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(time::Duration::days(SESSION_DAYS))
+        .finish();
+    Ok(HttpResponse::Ok().cookie(cookie).json(Session {
+        token,
+        user: user.into(),
+    }))
+}
+
+#[actix_web::post("/logout")]
+async fn logout(data: web::Data<AppState>, req: HttpRequest) -> Result<impl Responder> {
+    if let Some(token) = session_token(&req) {
+        data.lock().delete_session(&auth::token_hash(&token))?;
+    }
+    let mut cookie = Cookie::named(SESSION_COOKIE);
+    cookie.set_path("/");
+    let mut response = HttpResponse::NoContent().finish();
+    response.add_removal_cookie(&cookie).ok();
+    Ok(response)
+}
+
+#[actix_web::get("/me")]
+async fn get_me(user: CurrentUser) -> impl Responder {
+    web::Json(UserInfo::from(user.0))
+}
+
+#[derive(Deserialize)]
+struct NewPassword {
+    password: String,
+}
+
+#[actix_web::put("/me/password")]
+async fn set_password(
+    data: web::Data<AppState>,
+    user: CurrentUser,
+    password: web::Json<NewPassword>,
+) -> Result<impl Responder> {
+    let hash = hash_password(&password.password)?;
+    data.lock().set_password_hash(user.0.id, &hash)?;
+    Ok(HttpResponse::NoContent())
+}
+
+#[actix_web::get("/users")]
+async fn get_users(data: web::Data<AppState>, _admin: Admin) -> Result<impl Responder> {
+    let users: Vec<UserInfo> = data
+        .lock()
+        .get_users()?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(web::Json(users))
+}
+
+#[derive(Deserialize)]
+struct NewUser {
+    name: String,
+    password: String,
+    #[serde(default)]
+    admin: bool,
+}
+
+#[actix_web::post("/users")]
+async fn add_user(
+    data: web::Data<AppState>,
+    _admin: Admin,
+    user: web::Json<NewUser>,
+) -> Result<impl Responder> {
+    if user.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("Name must not be empty".to_owned()));
+    }
+    let hash = hash_password(&user.password)?;
+    let db = data.lock();
+    if db.get_user_by_name(&user.name).is_ok() {
+        return Err(ApiError::Conflict(format!(
+            "A user named '{}' already exists",
+            user.name
+        )));
+    }
+    let user = user.into_inner();
+    let id = db.create_user(&user.name, Some(&hash), user.admin)?;
+    Ok(HttpResponse::Created().json(UserInfo {
+        id,
+        name: user.name,
+        admin: user.admin,
+    }))
+}
+
+#[actix_web::delete("/users/{id}")]
+async fn delete_user(
+    data: web::Data<AppState>,
+    admin: Admin,
+    id: web::Path<i64>,
+) -> Result<impl Responder> {
+    if *id == admin.0.id {
+        return Err(ApiError::BadRequest(
+            "Admins can't delete themselves".to_owned(),
+        ));
+    }
+    if *id == DEFAULT_USER {
+        return Err(ApiError::BadRequest(
+            "The default user can't be deleted".to_owned(),
+        ));
+    }
+    data.lock().delete_user(*id)?;
+    Ok(HttpResponse::NoContent())
+}
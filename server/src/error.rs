@@ -0,0 +1,130 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use timetrax::{business_logic, database, export};
+
+#[derive(Debug)]
+pub enum ApiError {
+    Db(database::Error),
+    Logic(business_logic::Error),
+    BadRequest(String),
+    Conflict(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Internal(String),
+}
+
+impl From<database::Error> for ApiError {
+    fn from(value: database::Error) -> Self {
+        Self::Db(value)
+    }
+}
+
+impl From<business_logic::Error> for ApiError {
+    fn from(value: business_logic::Error) -> Self {
+        match value {
+            business_logic::Error::DbError(e) => Self::Db(e),
+            e => Self::Logic(e),
+        }
+    }
+}
+
+impl From<export::Error> for ApiError {
+    fn from(value: export::Error) -> Self {
+        match value {
+            export::Error::Logic(e) => e.into(),
+            export::Error::Csv(e) => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Db(e) => e.fmt(f),
+            Self::Logic(e) => e.fmt(f),
+            Self::BadRequest(e) => e.fmt(f),
+            Self::Conflict(e) => e.fmt(f),
+            Self::Unauthorized(e) => e.fmt(f),
+            Self::Forbidden(e) => e.fmt(f),
+            Self::Internal(e) => e.fmt(f),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Db(database::Error::Sqlite(rusqlite::Error::SqliteFailure(e, _)))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                "conflict"
+            }
+            Self::Db(database::Error::Sqlite(_)) => "database",
+            Self::Db(database::Error::SchemaTooNew { .. }) => "schema_too_new",
+            Self::Db(database::Error::NoSuchEntry(_)) => "no_such_entry",
+            Self::Db(database::Error::NoSuchItem(_)) => "no_such_item",
+            Self::Db(database::Error::DuplicateEntry(_)) => "duplicate_entry",
+            Self::Db(database::Error::InvalidMove(_)) => "invalid_move",
+            Self::Db(database::Error::MalformedDay(_)) => "malformed_day",
+            Self::Db(database::Error::NoSuchUser(_)) => "no_such_user",
+            Self::Db(database::Error::InvalidParent(_)) => "invalid_parent",
+            Self::Db(database::Error::Overlap(_)) => "overlap",
+            Self::Db(database::Error::InvalidInterval(_)) => "invalid_interval",
+            Self::Logic(business_logic::Error::Inconsistent(_)) => "inconsistent",
+            Self::Logic(business_logic::Error::CannotRepair(_)) => "cannot_repair",
+            Self::Logic(business_logic::Error::InvalidValue(_)) => "invalid_value",
+            Self::Logic(business_logic::Error::DbError(_)) => "database",
+            Self::BadRequest(_) => "bad_request",
+            Self::Conflict(_) => "conflict",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        use business_logic::Error as Logic;
+        use database::Error as Db;
+        match self {
+            Self::Db(Db::NoSuchEntry(_) | Db::NoSuchItem(_) | Db::NoSuchUser(_)) => {
+                StatusCode::NOT_FOUND
+            }
+            Self::Db(Db::DuplicateEntry(_) | Db::Overlap(_)) | Self::Conflict(_) => {
+                StatusCode::CONFLICT
+            }
+            Self::Db(Db::Sqlite(rusqlite::Error::SqliteFailure(e, _)))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                StatusCode::CONFLICT
+            }
+            Self::Db(
+                Db::InvalidMove(_)
+                | Db::MalformedDay(_)
+                | Db::InvalidInterval(_)
+                | Db::InvalidParent(_),
+            )
+            | Self::Logic(Logic::Inconsistent(_) | Logic::CannotRepair(_)) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Self::Logic(Logic::InvalidValue(_)) | Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+        })
+    }
+}
@@ -0,0 +1,62 @@
+//! Push channel that tells clients about changes made through the API, so that other tabs
+//! and devices can refresh without polling.
+use actix_web::{web, HttpResponse, Responder};
+use chrono::NaiveDate;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::StreamExt;
+
+use crate::auth::CurrentUser;
+use crate::AppState;
+
+/// Events kept for slow clients before they miss some
+const CAPACITY: usize = 64;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    CurrentWork {
+        work_item: Option<u64>,
+    },
+    WorkItems,
+    Times {
+        date: NaiveDate,
+    },
+    ExpectedTime {
+        date: NaiveDate,
+    },
+    /// Days start and end elsewhere now
+    Timezone,
+    /// Expected times from today on were recomputed
+    HolidayRegion,
+    /// The client missed events and has to reload everything
+    Lagged,
+}
+
+pub fn channel() -> broadcast::Sender<(i64, Event)> {
+    broadcast::channel(CAPACITY).0
+}
+
+fn to_message(event: &Event) -> web::Bytes {
+    let data = serde_json::to_string(event).expect("Events are always serializable");
+    web::Bytes::from(format!("data: {}\n\n", data))
+}
+
+/// Server-Sent Events stream of all changes to the data of the user
+#[actix_web::get("/events")]
+async fn events(data: web::Data<AppState>, user: CurrentUser) -> impl Responder {
+    let user = user.0.id;
+    let stream = BroadcastStream::new(data.subscribe()).filter_map(move |event| {
+        let event = match event {
+            Ok((owner, event)) if owner == user => event,
+            Ok(_) => return None,
+            Err(BroadcastStreamRecvError::Lagged(_)) => Event::Lagged,
+        };
+        Some(Ok::<_, actix_web::Error>(to_message(&event)))
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
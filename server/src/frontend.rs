@@ -0,0 +1,31 @@
+//! Web frontend, compiled into the binary so the server doesn't depend on its working directory
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+
+const INDEX_HTML: &str = include_str!("../frontend/index.html");
+const APP_JS: &str = include_str!("../frontend/app.js");
+const STYLE_CSS: &str = include_str!("../frontend/style.css");
+
+pub fn services(cfg: &mut web::ServiceConfig) {
+    cfg.service(index).service(app_js).service(style_css);
+}
+
+#[actix_web::get("/")]
+async fn index() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(INDEX_HTML)
+}
+
+#[actix_web::get("/app.js")]
+async fn app_js() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/javascript; charset=utf-8")
+        .body(APP_JS)
+}
+
+#[actix_web::get("/style.css")]
+async fn style_css() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(STYLE_CSS)
+}
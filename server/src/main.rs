@@ -1,45 +1,687 @@
-use actix_web::{web, Either, Responder};
-use serde::Serialize;
+mod api;
+mod auth;
+mod error;
+mod events;
+mod frontend;
 
-struct AppState {
-    db: timetrax::database::Database<'static, chrono::Utc>,
-}
+use events::Event;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use timetrax::config::{Config, Overrides};
+use timetrax::database::{Database, User, DEFAULT_USER};
+use tokio::sync::broadcast;
 
-#[derive(Serialize, Debug, Clone)]
-struct WorkItem {
-    id: u64,
-    title: String,
+/// State shared by all workers. A single connection serializes all database access, so
+/// concurrent requests can't interleave their reads and writes.
+pub struct AppState {
+    db: Mutex<Database<'static, chrono::Utc>>,
+    /// Events with the id of the user whose data changed
+    events: broadcast::Sender<(i64, Event)>,
 }
 
-#[actix_web::get("/work_items")]
-async fn get_work_items(data: actix_web::web::Data<AppState>) -> impl Responder {
-    let work_items = &data.db.get_available_work();
-    if let Ok(items) = work_items {
-        let items: Vec<_> = items
-            .iter()
-            .cloned()
-            .map(|(title, id)| WorkItem { id, title })
-            .collect();
-        let res = web::Json(items);
-        Either::Left(res)
-    } else {
-        Either::Right(actix_web::HttpResponse::InternalServerError())
+impl AppState {
+    pub fn new(db: Database<'static, chrono::Utc>) -> Self {
+        Self {
+            db: Mutex::new(db),
+            events: events::channel(),
+        }
+    }
+
+    /// Tell all clients of a user about a change
+    pub fn notify(&self, user: &User, event: Event) {
+        // Fails only if no client is listening
+        let _ = self.events.send((user.id, event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<(i64, Event)> {
+        self.events.subscribe()
+    }
+
+    /// Database for the accounts and sessions, with [`DEFAULT_USER`] selected
+    pub fn lock(&self) -> MutexGuard<'_, Database<'static, chrono::Utc>> {
+        // A panicking handler doesn't leave the database in an invalid state
+        let mut db = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+        // Don't keep the user selected by the previous request
+        db.set_user(DEFAULT_USER);
+        db
+    }
+
+    /// Database working on the data of `user`
+    pub fn db(&self, user: &User) -> MutexGuard<'_, Database<'static, chrono::Utc>> {
+        let mut db = self.lock();
+        db.set_user(user.id);
+        db
     }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let port = 8080;
+    let config = Overrides::from_args(std::env::args().skip(1))
+        .and_then(Config::load)
+        .map_err(std::io::Error::other)?;
+    if let Some(legacy) = config.prepare_database().map_err(std::io::Error::other)? {
+        println!(
+            "Moved {} to {}",
+            legacy.display(),
+            config.database.display()
+        );
+    }
+    let db = Database::open(&config.database, &chrono::Utc).map_err(std::io::Error::other)?;
+    let app_state = actix_web::web::Data::new(AppState::new(db));
+    let static_dir = config.server.static_dir.clone();
     actix_web::HttpServer::new(move || {
-        let db = timetrax::database::Database::open("work.db", &chrono::Utc).unwrap();
-        let app_state = AppState { db };
-        let api = actix_web::web::scope("/api").service(get_work_items);
-        actix_web::App::new()
-            .app_data(actix_web::web::Data::new(app_state))
+        let api = actix_web::web::scope("/api").configure(api::services);
+        let mut app = actix_web::App::new()
+            .app_data(app_state.clone())
             .service(api)
-            .service(actix_files::Files::new("/static", "./static"))
+            .configure(frontend::services);
+        if let Some(dir) = &static_dir {
+            app = app.service(actix_files::Files::new("/static", dir));
+        }
+        app
     })
-    .bind(("127.0.0.1", port))?
+    .bind((config.server.bind.as_str(), config.server.port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{api, frontend, AppState};
+    use actix_web::{test, web, App};
+    use timetrax::auth;
+    use timetrax::database::{Database, DEFAULT_USER};
+
+    fn state() -> web::Data<AppState> {
+        web::Data::new(AppState::new(
+            Database::open(":memory:", &chrono::Utc).unwrap(),
+        ))
+    }
+
+    /// Authorization header of a new session of the default user
+    fn login(state: &AppState) -> (&'static str, String) {
+        let token = auth::new_session_token();
+        state
+            .lock()
+            .create_session(&auth::token_hash(&token), DEFAULT_USER)
+            .unwrap();
+        ("Authorization", format!("Bearer {}", token))
+    }
+
+    #[actix_web::test]
+    async fn repeated_switch_adds_single_entry() {
+        let state = state();
+        let auth = login(&state);
+        state.lock().add_work_item("test").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        for _ in 0..10 {
+            let req = test::TestRequest::put()
+                .uri("/api/current_work")
+                .insert_header(auth.clone())
+                .set_json(serde_json::json!({ "work_item": 1 }))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+        let today = state.lock().today().unwrap();
+        assert_eq!(state.lock().get_work_on_date(&today).unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn concurrent_switches_add_single_entry() {
+        let state = state();
+        let auth = login(&state);
+        state.lock().add_work_item("test").unwrap();
+        let workers = 8;
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(workers));
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let (state, auth, barrier) = (state.clone(), auth.clone(), barrier.clone());
+                // Every worker thread of the server runs its own system
+                std::thread::spawn(move || {
+                    actix_web::rt::System::new().block_on(async move {
+                        let app = test::init_service(
+                            App::new()
+                                .app_data(state)
+                                .service(web::scope("/api").configure(api::services)),
+                        )
+                        .await;
+                        barrier.wait();
+                        for _ in 0..10 {
+                            let req = test::TestRequest::put()
+                                .uri("/api/current_work")
+                                .insert_header(auth.clone())
+                                .set_json(serde_json::json!({ "work_item": 1 }))
+                                .to_request();
+                            let resp = test::call_service(&app, req).await;
+                            assert!(resp.status().is_success());
+                        }
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let today = state.lock().today().unwrap();
+        assert_eq!(state.lock().get_work_on_date(&today).unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn json_errors() {
+        let state = state();
+        let auth = login(&state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::put()
+            .uri("/api/work_items/42")
+            .insert_header(auth)
+            .set_json(serde_json::json!({ "visible": false }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::NOT_FOUND);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "no_such_item");
+    }
+
+    #[actix_web::test]
+    async fn manage_work_items() {
+        let state = state();
+        let auth = login(&state);
+        state.lock().add_work_item("test").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::put()
+            .uri("/api/work_items/1")
+            .insert_header(auth.clone())
+            .set_json(serde_json::json!({ "description": "A test", "archived": true }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/api/work_items")
+            .insert_header(auth.clone())
+            .to_request();
+        let items: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(items, serde_json::json!([]));
+        let req = test::TestRequest::get()
+            .uri("/api/work_items?all=true")
+            .insert_header(auth.clone())
+            .to_request();
+        let items: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            items,
+            serde_json::json!([{
+                "id": 1,
+                "parent": null,
+                "title": "test",
+                "path": "test",
+                "description": "A test",
+                "visible": true,
+                "archived": true,
+            }])
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/work_items")
+            .insert_header(auth.clone())
+            .set_json(serde_json::json!({ "title": "sub", "parent": 1 }))
+            .to_request();
+        let item: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(item["path"], "test / sub");
+        let req = test::TestRequest::put()
+            .uri("/api/work_items/1")
+            .insert_header(auth.clone())
+            .set_json(serde_json::json!({ "title": "renamed", "parent": 2 }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            state.lock().get_work_item_names().unwrap()[&1],
+            "test",
+            "Rejected update changes nothing"
+        );
+        let req = test::TestRequest::put()
+            .uri("/api/work_items/2")
+            .insert_header(auth)
+            .set_json(serde_json::json!({ "parent": null }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn edit_day() {
+        let state = state();
+        let auth = login(&state);
+        state.lock().add_work_item("test").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::put()
+            .uri("/api/timezone")
+            .set_json(serde_json::json!({ "timezone": "Pacific/Auckland" }))
+            .insert_header(auth.clone())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/api/timezone")
+            .insert_header(auth.clone())
+            .to_request();
+        let timezone: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            timezone,
+            serde_json::json!({ "timezone": "Pacific/Auckland", "configured": true })
+        );
+        let today = state.lock().today().unwrap();
+        let at = |hour| {
+            chrono::TimeZone::from_local_datetime(
+                &chrono_tz::Pacific::Auckland,
+                &today.and_hms_opt(hour, 0, 0).unwrap(),
+            )
+            .unwrap()
+            .to_rfc3339()
+        };
+        let requests = [
+            test::TestRequest::post()
+                .uri("/api/switches")
+                .set_json(serde_json::json!({ "at": at(8), "work_item": 1 })),
+            test::TestRequest::post()
+                .uri("/api/switches")
+                .set_json(serde_json::json!({ "at": at(9), "work_item": null })),
+            test::TestRequest::put()
+                .uri("/api/switches")
+                .set_json(serde_json::json!({ "from": at(9), "to": at(10) })),
+        ];
+        for req in requests {
+            let req = req.insert_header(auth.clone()).to_request();
+            let resp = test::call_service(&app, req).await;
+            assert!(resp.status().is_success());
+        }
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/days/{}", today))
+            .insert_header(auth.clone())
+            .to_request();
+        let day: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(day["entries"].as_array().unwrap().len(), 2);
+        let req = test::TestRequest::get()
+            .uri("/api/leave_at")
+            .insert_header(auth.clone())
+            .to_request();
+        let leave_at: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(leave_at.get("even_day").is_some() && leave_at.get("even_balance").is_some());
+
+        assert_eq!(day["items"][0]["seconds"], 7200);
+        assert_eq!(day["work_done"], 7200);
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/api/work_item_totals?from={}&to={}&group=week",
+                today, today
+            ))
+            .insert_header(auth.clone())
+            .to_request();
+        let totals: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            totals[0]["items"],
+            serde_json::json!([{ "work_item": 1, "seconds": 7200 }])
+        );
+
+        let req = test::TestRequest::put()
+            .uri("/api/intervals/annotation")
+            .set_json(serde_json::json!({ "start": at(8), "note": "review", "tags": ["billable"] }))
+            .insert_header(auth.clone())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/days/{}", today))
+            .insert_header(auth.clone())
+            .to_request();
+        let day: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(day["entries"][0]["note"], "review");
+        assert_eq!(day["entries"][0]["tags"], serde_json::json!(["billable"]));
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/api/work_item_totals?from={}&to={}&tag=meeting",
+                today, today
+            ))
+            .insert_header(auth.clone())
+            .to_request();
+        let totals: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(totals, serde_json::json!([]));
+
+        let req = test::TestRequest::delete()
+            .uri(&format!(
+                "/api/intervals?start={}",
+                at(8).replace('+', "%2B")
+            ))
+            .insert_header(auth)
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        assert!(state.lock().get_work_on_date(&today).unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn holiday_region() {
+        let state = state();
+        let auth = login(&state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::put()
+            .uri("/api/holiday_region")
+            .insert_header(auth.clone())
+            .set_json(serde_json::json!({ "region": "Bayern" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/api/holiday_region")
+            .insert_header(auth.clone())
+            .to_request();
+        let region: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(region, serde_json::json!({ "region": "Bayern" }));
+        let req = test::TestRequest::put()
+            .uri("/api/holiday_region")
+            .insert_header(auth)
+            .set_json(serde_json::json!({ "region": "Atlantis" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn export_csv() {
+        let state = state();
+        let auth = login(&state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/api/export/days.csv?from=2023-05-01&to=2023-05-02")
+            .insert_header(auth.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=\"timetrax-days-2023-05-01-2023-05-02.csv\""
+        );
+        let body = test::read_body(resp).await;
+        assert_eq!(body.split(|b| *b == b'\n').count(), 4);
+
+        let req = test::TestRequest::get()
+            .uri("/api/export/intervals.csv?from=2023-05-02&to=2023-05-01")
+            .insert_header(auth)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn timesheet() {
+        let state = state();
+        let auth = login(&state);
+        let app = test::init_service(
+            App::new()
+                .app_data(state)
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri("/api/timesheet/2023/5?format=md")
+            .insert_header(auth.clone())
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/markdown; charset=utf-8"
+        );
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).starts_with("# Timesheet 2023-05\n"));
+
+        for uri in ["/api/timesheet/2023/13", "/api/timesheet/2023/5?format=pdf"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(auth.clone())
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, req).await.status(),
+                actix_web::http::StatusCode::BAD_REQUEST
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn serves_frontend() {
+        let app = test::init_service(App::new().configure(frontend::services)).await;
+        let resp = test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert!(resp.status().is_success());
+        let resp =
+            test::call_service(&app, test::TestRequest::get().uri("/app.js").to_request()).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "text/javascript; charset=utf-8"
+        );
+    }
+
+    #[actix_web::test]
+    async fn push_changes_to_clients() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let state = state();
+        let (_, auth) = login(&state);
+        state.lock().add_work_item("test").unwrap();
+        let app_state = state.clone();
+        let server = actix_web::HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .service(web::scope("/api").configure(api::services))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        let mut listener = TcpStream::connect(addr).await.unwrap();
+        listener
+            .write_all(
+                format!(
+                    "GET /api/events HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\r\n",
+                    auth
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let mut received = String::new();
+        let mut buf = [0; 1024];
+        while !received.contains("text/event-stream") {
+            let n = listener.read(&mut buf).await.unwrap();
+            received.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+
+        let body = r#"{"work_item":1}"#;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client
+            .write_all(
+                format!(
+                    "PUT /api/current_work HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\
+                     Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    auth,
+                    body.len(),
+                    body
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let expected = r#"data: {"type":"current_work","work_item":1}"#;
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !received.contains(expected) {
+                let n = listener.read(&mut buf).await.unwrap();
+                assert!(n > 0, "Event stream closed");
+                received.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+        })
+        .await
+        .expect("No event received");
+    }
+
+    #[actix_web::test]
+    async fn accounts() {
+        use actix_web::http::StatusCode;
+
+        let state = state();
+        let hash = auth::hash_password("secret").unwrap();
+        state.lock().set_password_hash(DEFAULT_USER, &hash).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(web::scope("/api").configure(api::services)),
+        )
+        .await;
+        let resp = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/api/work_items").to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let login = |name: &str, password: &str| {
+            test::TestRequest::post()
+                .uri("/api/login")
+                .set_json(serde_json::json!({ "name": name, "password": password }))
+                .to_request()
+        };
+        let resp = test::call_service(&app, login("admin", "wrong")).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, login("admin", "secret")).await;
+        assert!(resp
+            .response()
+            .cookies()
+            .any(|c| c.name() == "timetrax_session"));
+        let session: serde_json::Value = test::read_body_json(resp).await;
+        let admin = (
+            "Authorization",
+            format!("Bearer {}", session["token"].as_str().unwrap()),
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/users")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "name": "user", "password": "pw" }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+        let session: serde_json::Value =
+            test::call_and_read_body_json(&app, login("user", "pw")).await;
+        let user = (
+            "Authorization",
+            format!("Bearer {}", session["token"].as_str().unwrap()),
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/work_items")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "title": "admin's" }))
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/api/work_items")
+            .insert_header(user.clone())
+            .to_request();
+        let items: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            items,
+            serde_json::json!([]),
+            "Users only see their own items"
+        );
+        let req = test::TestRequest::put()
+            .uri("/api/current_work")
+            .insert_header(user.clone())
+            .set_json(serde_json::json!({ "work_item": 1 }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/users")
+            .insert_header(user.clone())
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/api/users")
+            .insert_header(admin.clone())
+            .set_json(serde_json::json!({ "name": "second", "password": "pw", "admin": true }))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CREATED
+        );
+        let session: serde_json::Value =
+            test::call_and_read_body_json(&app, login("second", "pw")).await;
+        let second = (
+            "Authorization",
+            format!("Bearer {}", session["token"].as_str().unwrap()),
+        );
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/users/{}", DEFAULT_USER))
+            .insert_header(second)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert!(state.lock().get_user_by_name("admin").is_ok());
+
+        let req = test::TestRequest::post()
+            .uri("/api/logout")
+            .insert_header(user.clone())
+            .to_request();
+        assert!(test::call_service(&app, req).await.status().is_success());
+        let req = test::TestRequest::get()
+            .uri("/api/me")
+            .insert_header(user)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
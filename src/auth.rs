@@ -0,0 +1,64 @@
+//! Password hashing and session tokens for user accounts
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, SaltString};
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+use rand_core::RngCore;
+use sha2::{Digest, Sha256};
+
+pub use argon2::password_hash::Error;
+
+/// Hash in PHC string format, ready to be stored
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Random token handed to a client after login
+pub fn new_session_token() -> String {
+    let mut token = [0; 32];
+    OsRng.fill_bytes(&mut token);
+    to_hex(&token)
+}
+
+/// Only the hash of a token is stored, so a leaked database doesn't contain valid sessions
+pub fn token_hash(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_password, new_session_token, token_hash, verify_password};
+
+    #[test]
+    fn passwords() {
+        let hash = hash_password("secret").unwrap();
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("Secret", &hash));
+        assert!(!verify_password("secret", "not a hash"));
+        assert_ne!(hash, hash_password("secret").unwrap(), "Salted");
+    }
+
+    #[test]
+    fn tokens() {
+        let token = new_session_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_session_token());
+        assert_eq!(token_hash(&token), token_hash(&token));
+        assert_ne!(token_hash(&token), token);
+    }
+}
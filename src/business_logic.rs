@@ -1,10 +1,16 @@
-use crate::database::{Database, TimeProvider};
-use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
-use std::{collections::HashMap, num::ParseIntError};
+use crate::compliance;
+use crate::database::{Absence, AbsenceKind, Database, TimeProvider};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::{
+    collections::{HashMap, HashSet},
+    num::ParseIntError,
+};
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     Inconsistent(NaiveDate),
+    CannotRepair(NaiveDate),
     InvalidValue(ParseIntError),
     DbError(crate::database::Error),
 }
@@ -22,6 +28,7 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Inconsistent(d) => write!(f, "Inconsistent data on {}. No end of workday?", d),
+            Self::CannotRepair(d) => write!(f, "Repair strategy not applicable to {}", d),
             Self::InvalidValue(e) => e.fmt(f),
             Self::DbError(e) => e.fmt(f),
         }
@@ -45,7 +52,7 @@ impl Iterator for DateRange {
     type Item = NaiveDate;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current == self.end {
+        if self.current >= self.end {
             return None;
         }
         let res = self.current;
@@ -57,16 +64,21 @@ impl Iterator for DateRange {
 pub fn get_default_time<T: TimeProvider>(
     db: &Database<T>,
     date: NaiveDate,
-) -> Result<i64, rusqlite::Error> {
+) -> crate::database::Result<i64> {
+    if let Some(region) = db.get_holiday_region()? {
+        if region.is_holiday(date) {
+            return Ok(0);
+        }
+    }
+    if let Some(schedule) = db.get_work_schedule(date)? {
+        return Ok(schedule.seconds_on(date.weekday()));
+    }
     match date.weekday() {
         chrono::Weekday::Sat | chrono::Weekday::Sun => {
             return Ok(0);
         }
         _ => {}
     }
-    if holiday_de::GermanRegion::BadenWuerttemberg.is_holiday(date) {
-        return Ok(0);
-    }
     let default_time = db.get_kv::<i64>("default_time")?;
     Ok(default_time)
 }
@@ -84,10 +96,101 @@ pub fn get_expected_work_or_insert_default<T: TimeProvider>(
     })
 }
 
+/// Overwrite all stored expected times from `start` on with the defaults, e.g. after the
+/// holiday region was changed. This includes future days that were already filled in by reports
+/// or exports. Manually set values are lost.
+pub fn recompute_expected_time<T: TimeProvider>(
+    db: &Database<T>,
+    start: NaiveDate,
+) -> crate::database::Result<()> {
+    for date in db.get_expected_time_dates(start)? {
+        db.set_expected_time(date, get_default_time(db, date)?)?;
+    }
+    Ok(())
+}
+
+/// Part of the expected work that is covered by an absence
+pub fn absence_credit(expected: Duration, absence: Option<&Absence>) -> Duration {
+    match absence {
+        None
+        | Some(Absence {
+            kind: AbsenceKind::OvertimeCompensation,
+            ..
+        }) => Duration::zero(),
+        Some(Absence { half_day: true, .. }) => expected / 2,
+        Some(Absence {
+            half_day: false, ..
+        }) => expected,
+    }
+}
+
+/// Expected work on a day after subtracting the credit for absences
+pub fn get_expected_work_with_absence<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<Duration, Error> {
+    let expected = get_expected_work_or_insert_default(db, date)?;
+    let absence = db.get_absence(date)?;
+    Ok(expected - absence_credit(expected, absence.as_ref()))
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VacationBalance {
+    pub entitlement: f64,
+    pub taken: f64,
+}
+
+impl VacationBalance {
+    pub fn remaining(&self) -> f64 {
+        self.entitlement - self.taken
+    }
+}
+
+/// Vacation days taken and left in a year. Vacation on days without expected work is not counted.
+pub fn vacation_balance<T: TimeProvider>(
+    db: &Database<T>,
+    year: i32,
+) -> Result<VacationBalance, Error> {
+    let entitlement = db.get_vacation_entitlement(year)?.unwrap_or(0.0);
+    let mut taken = 0.0;
+    if let (Some(start), Some(end)) = (
+        NaiveDate::from_ymd_opt(year, 1, 1),
+        NaiveDate::from_ymd_opt(year + 1, 1, 1),
+    ) {
+        for (date, absence) in db.get_absences(start, end)? {
+            if absence.kind != AbsenceKind::Vacation || get_default_time(db, date)? == 0 {
+                continue;
+            }
+            taken += if absence.half_day { 0.5 } else { 1.0 };
+        }
+    }
+    Ok(VacationBalance { entitlement, taken })
+}
+
 #[derive(Debug, PartialEq)]
 pub struct WorkdayTime {
     pub work_done: Result<Duration, Error>,
+    /// Expected work, already reduced by the absence credit
     pub expected: Duration,
+    pub absence: Option<Absence>,
+}
+
+pub fn get_workday_time<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<WorkdayTime, Error> {
+    let deduct_breaks = compliance::deducts_breaks(db)?;
+    let work_done = db
+        .get_work_on_date(&date)
+        .map_err(Into::into)
+        .and_then(|x| work_times_to_duration(&x, deduct_breaks));
+    let expected = get_expected_work_or_insert_default(db, date)?;
+    let absence = db.get_absence(date)?;
+    Ok(WorkdayTime {
+        work_done,
+        expected: expected - absence_credit(expected, absence.as_ref()),
+        absence,
+    })
 }
 
 pub fn get_work_time_by_day<T: TimeProvider>(
@@ -95,26 +198,53 @@ pub fn get_work_time_by_day<T: TimeProvider>(
 ) -> Result<HashMap<NaiveDate, WorkdayTime>, Error> {
     let mut result = HashMap::new();
     if let Some(start_day) = db.get_start_day()? {
-        let today = db.now().with_timezone(&Local).date_naive();
+        let today = db.today()?;
+        result.extend(get_work_time_in_range(db, start_day, today)?);
+    }
+    Ok(result)
+}
+
+/// Results of all days from `start` (inclusive) to `end` (exclusive)
+pub fn get_work_time_in_range<T: TimeProvider>(
+    db: &Database<T>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<(NaiveDate, WorkdayTime)>, Error> {
+    DateRange::new(start, end)
+        .map(|date| Ok((date, get_workday_time(db, date)?)))
+        .collect()
+}
+
+/// Fill the daily summary cache for all days before today that are not cached yet
+pub fn update_daily_summaries<T: TimeProvider>(db: &Database<T>) -> Result<(), Error> {
+    if let Some(start_day) = db.get_start_day()? {
+        let today = db.today()?;
+        let cached: HashSet<_> = db
+            .get_summarized_dates(start_day, today)?
+            .into_iter()
+            .collect();
         for date in DateRange::new(start_day, today) {
-            let work_done = db
-                .get_work_on_date(&date)
-                .map_err(Into::into)
-                .and_then(|x| work_times_to_duration(&x));
-            let expected = get_expected_work_or_insert_default(db, date)?;
-            result.insert(
-                date,
-                WorkdayTime {
-                    work_done,
-                    expected,
-                },
-            );
+            if cached.contains(&date) {
+                continue;
+            }
+            let workday_time = get_workday_time(db, date)?;
+            let worked = match workday_time.work_done {
+                Ok(work) => Some(work),
+                Err(Error::Inconsistent(_)) => None,
+                Err(e) => return Err(e),
+            };
+            db.set_daily_summary(date, worked, workday_time.expected)?;
         }
     }
-    Ok(result)
+    Ok(())
 }
 
-fn work_times_to_duration(times: &[(Option<u64>, DateTime<Local>)]) -> Result<Duration, Error> {
+/// Work of a day with end of workday, optionally minus the part of the required breaks that
+/// was not taken
+fn work_times_to_duration(
+    times: &[(Option<u64>, DateTime<Tz>)],
+    deduct_breaks: bool,
+) -> Result<Duration, Error> {
     if let Some(last) = times.last() {
         if last.0.is_some() {
             // Last entry of the day should be NULL
@@ -131,155 +261,664 @@ fn work_times_to_duration(times: &[(Option<u64>, DateTime<Local>)]) -> Result<Du
             res = res + (end.1 - start.1);
         }
     }
+    if deduct_breaks {
+        res = res - compliance::break_deduction(times);
+    }
     Ok(res)
 }
 
-pub fn time_diff<T: TimeProvider>(db: &Database<T>) -> Result<Duration, Error> {
-    let times = get_work_time_by_day(db)?;
-    let mut res = Duration::zero();
-    for (_, workday_time) in times {
-        let work = workday_time.work_done?;
-        res = res + work - workday_time.expected;
+#[derive(Debug, PartialEq)]
+pub struct TimeDiff {
+    /// Sum of worked minus expected time over all consistent days
+    pub diff: Duration,
+    /// Days left out of `diff` because they have no end of workday
+    pub inconsistent_days: Vec<NaiveDate>,
+}
+
+pub fn time_diff<T: TimeProvider>(db: &Database<T>) -> Result<TimeDiff, Error> {
+    update_daily_summaries(db)?;
+    let today = db.today()?;
+    Ok(TimeDiff {
+        diff: db.get_summary_balance(today)?,
+        inconsistent_days: db.get_summary_inconsistent_days(today)?,
+    })
+}
+
+/// Account balance up to the start of today: the configured starting balance plus all
+/// past days, minus today's expected work. Adding today's work gives the current balance.
+pub fn balance_before_today<T: TimeProvider>(db: &Database<T>) -> Result<TimeDiff, Error> {
+    let account_start = db
+        .get_kv_optional::<i64>("account_start")?
+        .map(Duration::seconds)
+        .unwrap_or_else(Duration::zero);
+    let today = db.today()?;
+    let time_diff = time_diff(db)?;
+    Ok(TimeDiff {
+        diff: account_start + time_diff.diff - get_expected_work_with_absence(db, today)?,
+        inconsistent_days: time_diff.inconsistent_days,
+    })
+}
+
+/// Work done on a day. A still running interval counts until now.
+pub fn work_done_until_now<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<Duration, Error> {
+    let mut times = db.get_work_on_date(&date)?;
+    if let Some((Some(_), _)) = times.last() {
+        let now = db.local_time(&db.now())?;
+        if now.date_naive() != date {
+            return Err(Error::Inconsistent(date));
+        }
+        times.push((None, now));
     }
-    Ok(res)
+    work_times_to_duration(&times, compliance::deducts_breaks(db)?)
+}
+
+/// Work time per work item id
+pub type ItemTotals = HashMap<u64, Duration>;
+
+/// How `work_item_totals` splits a date range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    /// One group for the whole range
+    Range,
+    Day,
+    /// Weeks starting on Monday
+    Week,
+}
+
+/// Work per item on a day. A still running interval counts until now, an interval without
+/// end on another day is left out.
+pub fn work_item_durations<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<ItemTotals, Error> {
+    tagged_work_item_durations(db, date, None)
+}
+
+/// Like `work_item_durations`, but only counting intervals with `tag` if one is given
+fn tagged_work_item_durations<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+    tag: Option<&str>,
+) -> Result<ItemTotals, Error> {
+    let mut times = db.get_work_on_date(&date)?;
+    let now = db.local_time(&db.now())?;
+    if now.date_naive() == date {
+        times.push((None, now));
+    }
+    let annotations = match tag {
+        Some(_) => db.get_annotations_on_date(&date)?,
+        None => HashMap::new(),
+    };
+    let tagged = |start: &DateTime<Tz>| {
+        tag.is_none_or(|tag| {
+            annotations
+                .get(start)
+                .is_some_and(|a| a.tags.iter().any(|t| t == tag))
+        })
+    };
+    let mut durations = HashMap::new();
+    for work in times.windows(2) {
+        if let (Some(work_item), true) = (work[0].0, tagged(&work[0].1)) {
+            let total = durations.entry(work_item).or_insert_with(Duration::zero);
+            *total = *total + (work[1].1 - work[0].1);
+        }
+    }
+    Ok(durations)
+}
+
+/// Work per item on the days from `start` (inclusive) to `end` (exclusive), keyed by the first
+/// day of each group in ascending order. Groups without work are left out. With a `tag` only
+/// intervals that have it count.
+pub fn work_item_totals<T: TimeProvider>(
+    db: &Database<T>,
+    start: NaiveDate,
+    end: NaiveDate,
+    grouping: Grouping,
+    tag: Option<&str>,
+) -> Result<Vec<(NaiveDate, ItemTotals)>, Error> {
+    let mut groups: Vec<(NaiveDate, ItemTotals)> = Vec::new();
+    for date in DateRange::new(start, end) {
+        let durations = tagged_work_item_durations(db, date, tag)?;
+        if durations.is_empty() {
+            continue;
+        }
+        let key = match grouping {
+            Grouping::Range => start,
+            Grouping::Day => date,
+            Grouping::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+        };
+        let group = match groups.last_mut() {
+            Some((last, group)) if *last == key => group,
+            _ => {
+                groups.push((key, HashMap::new()));
+                &mut groups.last_mut().unwrap().1
+            }
+        };
+        for (work_item, duration) in durations {
+            let total = group.entry(work_item).or_insert_with(Duration::zero);
+            *total = *total + duration;
+        }
+    }
+    Ok(groups)
+}
+
+/// Add the time of every item to all its parents, so each item has the total of its subtree
+pub fn roll_up_totals<T: TimeProvider>(
+    db: &Database<T>,
+    totals: &ItemTotals,
+) -> Result<ItemTotals, Error> {
+    let parents: HashMap<u64, Option<u64>> = db
+        .get_work_items()?
+        .into_iter()
+        .map(|item| (item.id, item.parent))
+        .collect();
+    let mut rolled_up = ItemTotals::new();
+    for (work_item, duration) in totals {
+        let mut current = Some(*work_item);
+        while let Some(id) = current {
+            let total = rolled_up.entry(id).or_insert_with(Duration::zero);
+            *total = *total + *duration;
+            current = parents.get(&id).copied().flatten();
+        }
+    }
+    Ok(rolled_up)
+}
+
+/// Current account balance including the work done today so far
+pub fn current_balance<T: TimeProvider>(db: &Database<T>) -> Result<TimeDiff, Error> {
+    let today = db.today()?;
+    let balance = balance_before_today(db)?;
+    Ok(TimeDiff {
+        diff: balance.diff + work_done_until_now(db, today)?,
+        inconsistent_days: balance.inconsistent_days,
+    })
+}
+
+/// Earliest times to end today's work, None if already reached
+#[derive(Debug, PartialEq)]
+pub struct LeaveAt {
+    /// Today's work equals today's expected work
+    pub even_day: Option<DateTime<Tz>>,
+    /// The account balance is zero
+    pub even_balance: Option<DateTime<Tz>>,
+}
+
+/// When today's work may end, assuming it continues now without interruption apart from the
+/// breaks the working time law still requires
+pub fn leave_at<T: TimeProvider>(db: &Database<T>) -> Result<LeaveAt, Error> {
+    leave_at_with_balance(db, balance_before_today(db)?.diff)
+}
+
+/// Like [`leave_at`] with the result of [`balance_before_today`] computed before, so that
+/// frequent updates only look at today
+pub fn leave_at_with_balance<T: TimeProvider>(
+    db: &Database<T>,
+    balance: Duration,
+) -> Result<LeaveAt, Error> {
+    let now = db.local_time(&db.now())?;
+    let today = now.date_naive();
+    let mut times = db.get_work_on_date(&today)?;
+    // A break or end of workday until now counts as a break
+    times.push((None, now));
+    let (worked, breaks) =
+        compliance::work_and_breaks(&times).unwrap_or((Duration::zero(), Duration::zero()));
+    let leave = |remaining: Duration| {
+        if remaining <= Duration::zero() {
+            return None;
+        }
+        let mut missing_break =
+            (compliance::required_break(worked + remaining) - breaks).max(Duration::zero());
+        if missing_break > Duration::zero() {
+            missing_break = missing_break.max(compliance::min_break());
+        }
+        Some(now + remaining + missing_break)
+    };
+    let expected = get_expected_work_with_absence(db, today)?;
+    let balance = balance + worked;
+    Ok(LeaveAt {
+        even_day: leave(expected - worked),
+        even_balance: leave(-balance),
+    })
+}
+
+pub fn inconsistent_days<T: TimeProvider>(db: &Database<T>) -> Result<Vec<NaiveDate>, Error> {
+    Ok(time_diff(db)?.inconsistent_days)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepairStrategy {
+    /// End the day at the last recorded shutdown, if it happened on that day
+    LastShutdown,
+    /// End the day as soon as the expected work of the day was done
+    ExpectedDuration,
+    /// End the day at the given time
+    At(DateTime<Utc>),
+}
+
+/// Add the missing end of workday to an inconsistent day
+pub fn repair_day<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+    strategy: &RepairStrategy,
+) -> Result<(), Error> {
+    let times = db.get_work_on_date(&date)?;
+    let last_start = match times.last() {
+        Some((Some(_), start)) => *start,
+        _ => return Err(Error::CannotRepair(date)),
+    };
+    let end = match strategy {
+        RepairStrategy::LastShutdown => {
+            let zone = db.timezone()?;
+            db.get_kv_optional::<DateTime<Utc>>("shutdown")?
+                .map(|t| t.with_timezone(&zone))
+                .filter(|t| t.date_naive() == date && *t > last_start)
+                .ok_or(Error::CannotRepair(date))?
+        }
+        RepairStrategy::ExpectedDuration => {
+            let mut closed = times.clone();
+            closed.push((None, last_start));
+            let worked = work_times_to_duration(&closed, compliance::deducts_breaks(db)?)?;
+            let remaining = get_expected_work_with_absence(db, date)? - worked;
+            let day_end = db.day_start(date + Duration::days(1))? - Duration::seconds(1);
+            (last_start + remaining.max(Duration::zero())).min(day_end)
+        }
+        RepairStrategy::At(t) if db.local_date(t)? == date => db.local_time(t)?,
+        RepairStrategy::At(_) => return Err(Error::CannotRepair(date)),
+    };
+    db.insert_work_switch(end.with_timezone(&Utc), None)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{get_work_time_by_day, work_times_to_duration};
+    use super::{
+        balance_before_today, current_balance, get_expected_work_or_insert_default,
+        get_work_time_by_day, inconsistent_days, leave_at, leave_at_with_balance,
+        recompute_expected_time, repair_day, roll_up_totals, time_diff, vacation_balance,
+        work_done_until_now, work_item_totals, work_times_to_duration, Grouping, LeaveAt,
+        RepairStrategy,
+    };
     use super::{Database, WorkdayTime};
-    use crate::database::{tests::MockTime, TimeProvider};
+    use crate::database::{tests::MockTime, Absence, AbsenceKind, TimeProvider, WorkSchedule};
     use chrono::{Duration, NaiveDate, TimeZone};
     #[test]
     fn test_work_times_to_duration() {
         assert_eq!(
-            work_times_to_duration(&[]).unwrap(),
+            work_times_to_duration(&[], false).unwrap(),
             chrono::Duration::zero(),
             "Empty work times"
         );
         assert_eq!(
-            work_times_to_duration(&vec![(
-                Some(1),
-                chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-            )]),
+            work_times_to_duration(
+                &vec![(
+                    Some(1),
+                    chrono_tz::UTC
+                        .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                        .unwrap()
+                )],
+                false
+            ),
             Err(super::Error::Inconsistent(
                 NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
             )),
             "Single value, no end time"
         );
         assert_eq!(
-            work_times_to_duration(&vec![
-                (
-                    Some(1),
-                    chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-                ),
-                (
-                    Some(1),
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
-                        .unwrap()
-                ),
-            ]),
+            work_times_to_duration(
+                &vec![
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
+                            .unwrap()
+                    ),
+                ],
+                false
+            ),
             Err(super::Error::Inconsistent(
                 NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
             )),
             "Two values, no end time"
         );
         assert_eq!(
-            work_times_to_duration(&vec![
-                (
-                    Some(1),
-                    chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-                ),
-                (
-                    None,
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 9, 30, 0)
-                        .unwrap()
-                ),
-                (
-                    Some(1),
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
-                        .unwrap()
-                ),
-            ]),
+            work_times_to_duration(
+                &vec![
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        None,
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 30, 0)
+                            .unwrap()
+                    ),
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
+                            .unwrap()
+                    ),
+                ],
+                false
+            ),
             Err(super::Error::Inconsistent(
                 NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
             )),
             "Two values, no end time, with break"
         );
         assert_eq!(
-            work_times_to_duration(&vec![
-                (
-                    Some(1),
-                    chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-                ),
-                (
-                    None,
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
-                        .unwrap()
-                ),
-            ])
+            work_times_to_duration(
+                &vec![
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        None,
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
+                            .unwrap()
+                    ),
+                ],
+                false
+            )
             .unwrap(),
             chrono::Duration::hours(1),
             "Single value, with end time"
         );
         assert_eq!(
-            work_times_to_duration(&vec![
-                (
-                    Some(1),
-                    chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-                ),
-                (
-                    Some(2),
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
-                        .unwrap()
-                ),
-                (
-                    None,
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 11, 0, 0)
-                        .unwrap()
-                ),
-            ])
+            work_times_to_duration(
+                &vec![
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        Some(2),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        None,
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 11, 0, 0)
+                            .unwrap()
+                    ),
+                ],
+                false
+            )
             .unwrap(),
             chrono::Duration::hours(2),
             "Two values, with end time"
         );
         assert_eq!(
-            work_times_to_duration(&vec![
-                (
-                    Some(1),
-                    chrono::Local.with_ymd_and_hms(2000, 1, 1, 9, 0, 0).unwrap()
-                ),
-                (
-                    None,
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
-                        .unwrap()
-                ),
-                (
-                    Some(2),
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 10, 30, 0)
-                        .unwrap()
-                ),
-                (
-                    None,
-                    chrono::Local
-                        .with_ymd_and_hms(2000, 1, 1, 11, 30, 0)
-                        .unwrap()
-                ),
-            ])
+            work_times_to_duration(
+                &vec![
+                    (
+                        Some(1),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 9, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        None,
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 0, 0)
+                            .unwrap()
+                    ),
+                    (
+                        Some(2),
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 10, 30, 0)
+                            .unwrap()
+                    ),
+                    (
+                        None,
+                        chrono_tz::UTC
+                            .with_ymd_and_hms(2000, 1, 1, 11, 30, 0)
+                            .unwrap()
+                    ),
+                ],
+                false
+            )
             .unwrap(),
             chrono::Duration::hours(2),
             "Two values, with end time, and break"
         );
     }
     #[test]
+    fn test_recompute_expected_time() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        let monday = t.now().date_naive();
+        let sunday = monday + Duration::days(6);
+        db.set_expected_time(monday, 60 * 60).unwrap();
+        db.set_expected_time(sunday, 60 * 60).unwrap();
+        let next_monday = monday + Duration::days(7);
+        db.set_expected_time(next_monday, 60 * 60).unwrap();
+        t.advance(24 * 3);
+        db.set_holiday_region(None).unwrap();
+        recompute_expected_time(&db, monday).unwrap();
+        assert_eq!(
+            db.get_expected_work(monday).unwrap(),
+            Some(Duration::hours(7))
+        );
+        assert_eq!(
+            db.get_expected_work(sunday).unwrap(),
+            Some(Duration::zero())
+        );
+        assert_eq!(
+            db.get_expected_work(next_monday).unwrap(),
+            Some(Duration::hours(7)),
+            "Future days that were already stored are updated too"
+        );
+        assert_eq!(
+            db.get_expected_work(monday + Duration::days(1)).unwrap(),
+            None,
+            "Days without stored value stay untouched"
+        );
+    }
+    #[test]
+    fn test_expected_work_from_schedule() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        let monday = t.now().date_naive();
+        let day = Duration::days(1);
+        db.set_work_schedule(&WorkSchedule {
+            valid_from: monday + day * 7,
+            seconds: [8 * 3600, 8 * 3600, 8 * 3600, 6 * 3600, 0, 2 * 3600, 0],
+        })
+        .unwrap();
+        let expected = |date| get_expected_work_or_insert_default(&db, date).unwrap();
+        assert_eq!(
+            expected(monday + day * 4),
+            Duration::hours(7),
+            "Default before schedule"
+        );
+        assert_eq!(
+            expected(monday + day * 5),
+            Duration::zero(),
+            "Default weekend"
+        );
+        assert_eq!(expected(monday + day * 7), Duration::hours(8));
+        assert_eq!(expected(monday + day * 10), Duration::hours(6));
+        assert_eq!(expected(monday + day * 11), Duration::zero());
+        assert_eq!(expected(monday + day * 12), Duration::hours(2));
+        t.advance(24 * 6);
+        db.set_work_schedule(&WorkSchedule {
+            valid_from: monday,
+            seconds: [3600; 7],
+        })
+        .unwrap();
+        assert_eq!(
+            expected(monday + day * 4),
+            Duration::hours(7),
+            "Expected time before today is not rewritten"
+        );
+        assert_eq!(
+            expected(monday + day * 7),
+            Duration::hours(8),
+            "Later schedule still applies"
+        );
+        db.remove_work_schedule(monday + day * 7).unwrap();
+        assert_eq!(
+            expected(monday + day * 7),
+            Duration::hours(1),
+            "Stored future day is recomputed"
+        );
+        assert_eq!(expected(monday + day * 6), Duration::hours(1), "Today");
+    }
+    #[test]
+    fn test_absences() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("test").unwrap();
+        let work_item = db.get_available_work().unwrap().first().unwrap().1;
+        let monday = t.now().date_naive();
+        let day = Duration::days(1);
+        let absence = |kind, half_day| Some(Absence { kind, half_day });
+        db.set_absence(monday, absence(AbsenceKind::Vacation, false).as_ref())
+            .unwrap();
+        db.set_absence(monday + day, absence(AbsenceKind::Sick, true).as_ref())
+            .unwrap();
+        db.set_absence(
+            monday + day * 2,
+            absence(AbsenceKind::OvertimeCompensation, false).as_ref(),
+        )
+        .unwrap();
+        db.set_absence(
+            monday + day * 3,
+            absence(AbsenceKind::Vacation, false).as_ref(),
+        )
+        .unwrap();
+        db.set_absence(
+            monday + day * 5,
+            absence(AbsenceKind::Vacation, false).as_ref(),
+        )
+        .unwrap();
+        db.set_absence(
+            monday + day * 7,
+            absence(AbsenceKind::Vacation, true).as_ref(),
+        )
+        .unwrap();
+        t.advance(24);
+        // Half day of work on the half sick day
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(3);
+        db.set_current_work(None).unwrap();
+        t.advance(21 + 24 * 2);
+        let times = get_work_time_by_day(&db).unwrap();
+        assert!(!times.contains_key(&monday), "Before start of tracking");
+        assert_eq!(times[&(monday + day)].expected, Duration::minutes(210));
+        assert_eq!(times[&(monday + day * 2)].expected, Duration::hours(7));
+        assert_eq!(
+            times[&(monday + day * 2)].absence,
+            absence(AbsenceKind::OvertimeCompensation, false)
+        );
+        assert_eq!(
+            time_diff(&db).unwrap().diff,
+            Duration::minutes(-30) - Duration::hours(7)
+        );
+
+        db.set_vacation_entitlement(1990, 30.0).unwrap();
+        let vacation = vacation_balance(&db, 1990).unwrap();
+        assert_eq!(vacation.taken, 2.5, "Weekends are not counted");
+        assert_eq!(vacation.remaining(), 27.5);
+    }
+    #[test]
+    fn test_work_item_totals() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        db.add_work_item("b").unwrap();
+        let items: std::collections::HashMap<_, _> =
+            db.get_available_work().unwrap().into_iter().collect();
+        let (a, b) = (items["a"], items["b"]);
+        let monday = t.now().date_naive();
+        let day = Duration::days(1);
+        // Monday: a 1h, b 2h
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(1);
+        db.set_current_work(Some(b)).unwrap();
+        let review = t.now();
+        t.advance(2);
+        db.set_current_work(None).unwrap();
+        // Tuesday: a 1h
+        t.advance(21);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(1);
+        db.set_current_work(None).unwrap();
+        // Next Monday: b 1h, then a still running for 2h
+        t.advance(6 * 24 - 1);
+        db.set_current_work(Some(b)).unwrap();
+        t.advance(1);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(2);
+
+        let hours = |h| Duration::hours(h);
+        let end = monday + day * 8;
+        let range = work_item_totals(&db, monday, end, Grouping::Range, None).unwrap();
+        assert_eq!(
+            range,
+            vec![(
+                monday,
+                std::collections::HashMap::from([(a, hours(4)), (b, hours(3))])
+            )]
+        );
+        let days = work_item_totals(&db, monday, end, Grouping::Day, None).unwrap();
+        assert_eq!(
+            days.iter().map(|(date, _)| *date).collect::<Vec<_>>(),
+            vec![monday, monday + day, monday + day * 7]
+        );
+        assert_eq!(days[1].1, std::collections::HashMap::from([(a, hours(1))]));
+        let weeks = work_item_totals(&db, monday + day, end, Grouping::Week, None).unwrap();
+        assert_eq!(
+            weeks,
+            vec![
+                (monday, std::collections::HashMap::from([(a, hours(1))])),
+                (
+                    monday + day * 7,
+                    std::collections::HashMap::from([(a, hours(2)), (b, hours(1))])
+                ),
+            ]
+        );
+
+        db.add_work_item("project").unwrap();
+        let project = db.get_available_work().unwrap()[2].1;
+        db.set_work_item_parent(a, Some(project)).unwrap();
+        let rolled_up = roll_up_totals(&db, &range[0].1).unwrap();
+        assert_eq!(
+            rolled_up,
+            std::collections::HashMap::from([(a, hours(4)), (b, hours(3)), (project, hours(4))])
+        );
+
+        db.set_interval_tags(review, &["review".to_owned()])
+            .unwrap();
+        assert_eq!(
+            work_item_totals(&db, monday, end, Grouping::Day, Some("review")).unwrap(),
+            vec![(monday, std::collections::HashMap::from([(b, hours(2))]))]
+        );
+    }
+    #[test]
     fn test_get_work_time_by_day() {
         let t = MockTime::new();
         let db = Database::open(":memory:", &t).unwrap();
@@ -312,6 +951,7 @@ mod tests {
                 WorkdayTime {
                     work_done: Ok(Duration::hours(1)),
                     expected: Duration::hours(5),
+                    absence: None,
                 },
             ),
             (
@@ -319,6 +959,7 @@ mod tests {
                 WorkdayTime {
                     work_done: Ok(Duration::hours(2)),
                     expected: Duration::hours(6),
+                    absence: None,
                 },
             ),
             (
@@ -326,10 +967,224 @@ mod tests {
                 WorkdayTime {
                     work_done: Err(super::Error::Inconsistent(start + day * 2)),
                     expected: Duration::hours(7),
+                    absence: None,
                 },
             ),
         ]);
         let res = get_work_time_by_day(&db).unwrap();
         assert_eq!(res, expected);
     }
+    #[test]
+    fn test_repair_day() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("test").unwrap();
+        let work_item = db.get_available_work().unwrap().first().unwrap().1;
+        let start = t.now().date_naive();
+        let day = Duration::days(1);
+        // Three days with a missing end, one correct day
+        for _ in 0..3 {
+            db.set_current_work(Some(work_item)).unwrap();
+            t.advance(2);
+            db.set_current_work(None).unwrap();
+            t.advance(1);
+            db.set_current_work(Some(work_item)).unwrap();
+            t.advance(21);
+        }
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(8);
+        db.set_current_work(None).unwrap();
+        db.shutdown().unwrap();
+        t.advance(16);
+        let diff = time_diff(&db).unwrap();
+        assert_eq!(diff.diff, Duration::hours(1), "Only the correct day counts");
+        assert_eq!(
+            diff.inconsistent_days,
+            vec![start, start + day, start + day * 2]
+        );
+
+        assert_eq!(
+            repair_day(&db, start, &RepairStrategy::LastShutdown),
+            Err(super::Error::CannotRepair(start)),
+            "Shutdown was on another day"
+        );
+        repair_day(&db, start, &RepairStrategy::ExpectedDuration).unwrap();
+        let end = db.get_work_on_date(&start).unwrap().last().unwrap().1;
+        assert_eq!(
+            end.with_timezone(&chrono::Utc),
+            chrono::Utc.with_ymd_and_hms(1990, 1, 1, 17, 0, 0).unwrap(),
+            "Two hours before the break, five after"
+        );
+        let at = (start + day)
+            .and_hms_opt(15, 0, 0)
+            .unwrap()
+            .and_local_timezone(chrono::Utc)
+            .unwrap();
+        repair_day(&db, start + day, &RepairStrategy::At(at)).unwrap();
+        assert_eq!(
+            repair_day(&db, start + day, &RepairStrategy::ExpectedDuration),
+            Err(super::Error::CannotRepair(start + day)),
+            "Already repaired"
+        );
+        assert_eq!(inconsistent_days(&db).unwrap(), vec![start + day * 2]);
+    }
+    #[test]
+    fn test_daily_summary_invalidation() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("test").unwrap();
+        let work_item = db.get_available_work().unwrap().first().unwrap().1;
+        let start = t.now().date_naive();
+        let at = |h| chrono::Utc.with_ymd_and_hms(1990, 1, 1, h, 0, 0).unwrap();
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(6);
+        db.set_current_work(None).unwrap();
+        t.advance(24);
+        assert_eq!(time_diff(&db).unwrap().diff, Duration::hours(-1));
+        assert_eq!(time_diff(&db).unwrap().diff, Duration::hours(-1), "Cached");
+
+        db.move_work_switch(at(15), at(16)).unwrap();
+        assert_eq!(
+            time_diff(&db).unwrap().diff,
+            Duration::zero(),
+            "Work edited"
+        );
+        db.set_expected_time(start, 8 * 60 * 60).unwrap();
+        assert_eq!(
+            time_diff(&db).unwrap().diff,
+            Duration::hours(-1),
+            "Expected edited"
+        );
+        db.set_absence(
+            start,
+            Some(&Absence {
+                kind: AbsenceKind::Sick,
+                half_day: true,
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            time_diff(&db).unwrap().diff,
+            Duration::hours(3),
+            "Absence added"
+        );
+        t.advance(-22);
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(22);
+        assert_eq!(
+            time_diff(&db).unwrap().inconsistent_days,
+            vec![start],
+            "Made inconsistent"
+        );
+    }
+    #[test]
+    fn test_time_diff_across_dst() {
+        let berlin = chrono_tz::Europe::Berlin;
+        // Work from midnight to 8:00 on the days the clocks are changed
+        for (date, worked) in [
+            (
+                NaiveDate::from_ymd_opt(2023, 3, 26).unwrap(),
+                Duration::hours(7),
+            ),
+            (
+                NaiveDate::from_ymd_opt(2023, 10, 29).unwrap(),
+                Duration::hours(9),
+            ),
+        ] {
+            let t = MockTime::new();
+            t.set_timezone(berlin);
+            let db = Database::open(":memory:", &t).unwrap();
+            db.add_work_item("test").unwrap();
+            let work_item = db.get_available_work().unwrap()[0].1;
+            let at = |hour| {
+                berlin
+                    .from_local_datetime(&date.and_hms_opt(hour, 0, 0).unwrap())
+                    .unwrap()
+                    .with_timezone(&chrono::Utc)
+            };
+            t.set(at(0));
+            db.set_current_work(Some(work_item)).unwrap();
+            t.set(at(8));
+            db.set_current_work(None).unwrap();
+            db.set_expected_time(date, 8 * 60 * 60).unwrap();
+            assert_eq!(db.get_work_on_date(&date).unwrap().len(), 2, "{}", date);
+            t.advance(24);
+            assert_eq!(
+                time_diff(&db).unwrap().diff,
+                worked - Duration::hours(8),
+                "{}",
+                date
+            );
+        }
+    }
+
+    #[test]
+    fn test_current_balance() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("test").unwrap();
+        let work_item = db.get_available_work().unwrap().first().unwrap().1;
+        let today = t.now().date_naive();
+        db.set_kv("account_start", 60 * 60).unwrap();
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(2);
+        assert_eq!(
+            work_done_until_now(&db, today).unwrap(),
+            Duration::hours(2),
+            "Running interval counts until now"
+        );
+        assert_eq!(current_balance(&db).unwrap().diff, Duration::hours(-4));
+        t.advance(24);
+        assert_eq!(
+            work_done_until_now(&db, today),
+            Err(super::Error::Inconsistent(today))
+        );
+    }
+
+    #[test]
+    fn test_leave_at() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("test").unwrap();
+        let work_item = db.get_available_work().unwrap()[0].1;
+        let start = t.now().with_timezone(&chrono_tz::UTC);
+        let at = |hours: i64, minutes: i64| {
+            Some(start + Duration::hours(hours) + Duration::minutes(minutes))
+        };
+        db.set_kv("account_start", 2 * 60 * 60).unwrap();
+        db.set_current_work(Some(work_item)).unwrap();
+        t.advance(3);
+        assert_eq!(
+            leave_at(&db).unwrap(),
+            LeaveAt {
+                // 7 hours of work need a break of 30 minutes
+                even_day: at(7, 30),
+                even_balance: at(5, 0),
+            }
+        );
+        db.set_current_work(None).unwrap();
+        t.advance(1);
+        assert_eq!(
+            leave_at(&db).unwrap(),
+            LeaveAt {
+                even_day: at(8, 0),
+                even_balance: at(6, 0),
+            }
+        );
+        let balance = balance_before_today(&db).unwrap().diff;
+        assert_eq!(
+            leave_at_with_balance(&db, balance + Duration::hours(1)).unwrap(),
+            LeaveAt {
+                even_day: at(8, 0),
+                even_balance: at(5, 0),
+            },
+            "Only today is taken from the database"
+        );
+        db.set_kv("account_start", 5 * 60 * 60).unwrap();
+        assert_eq!(leave_at(&db).unwrap().even_balance, None);
+    }
 }
@@ -0,0 +1,369 @@
+//! Checks of the German working time law (Arbeitszeitgesetz, ArbZG): breaks (§4), maximum work
+//! per day (§3) and rest between days (§5). Optionally the missing part of the required breaks
+//! is deducted from the work of a day.
+use crate::business_logic::Error;
+use crate::database::{Database, TimeProvider};
+use chrono::{DateTime, Duration, NaiveDate};
+use chrono_tz::Tz;
+
+const DEDUCT_BREAKS: &str = "deduct_breaks";
+
+/// Switches of a day as returned by `Database::get_work_on_date`
+type WorkTimes = Vec<(Option<u64>, DateTime<Tz>)>;
+
+/// Work per day may not exceed this, §3
+pub fn max_daily_work() -> Duration {
+    Duration::hours(10)
+}
+
+/// Rest between the end of work and the start of work on the next day, §5
+pub fn min_rest() -> Duration {
+    Duration::hours(11)
+}
+
+/// Shorter breaks don't count, §4
+pub fn min_break() -> Duration {
+    Duration::minutes(15)
+}
+
+/// Total breaks required for more work than the threshold, highest threshold first, §4
+fn break_rules() -> [(Duration, Duration); 2] {
+    [
+        (Duration::hours(9), Duration::minutes(45)),
+        (Duration::hours(6), Duration::minutes(30)),
+    ]
+}
+
+/// Total breaks required for the work of a day
+pub fn required_break(worked: Duration) -> Duration {
+    break_rules()
+        .into_iter()
+        .find(|(threshold, _)| worked > *threshold)
+        .map(|(_, required)| required)
+        .unwrap_or_else(Duration::zero)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// The breaks of at least 15 minutes are shorter than required
+    MissingBreak {
+        date: NaiveDate,
+        worked: Duration,
+        breaks: Duration,
+        required: Duration,
+    },
+    /// More than 10 hours of work
+    TooMuchWork { date: NaiveDate, worked: Duration },
+    /// Less than 11 hours between the end of the previous day and the start of `date`
+    ShortRest { date: NaiveDate, rest: Duration },
+}
+
+impl Violation {
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            Self::MissingBreak { date, .. }
+            | Self::TooMuchWork { date, .. }
+            | Self::ShortRest { date, .. } => *date,
+        }
+    }
+}
+
+fn minutes(duration: &Duration) -> String {
+    let minutes = duration.num_minutes();
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingBreak {
+                date,
+                worked,
+                breaks,
+                required,
+            } => write!(
+                f,
+                "{}: {} breaks for {} of work, {} required",
+                date,
+                minutes(breaks),
+                minutes(worked),
+                minutes(required)
+            ),
+            Self::TooMuchWork { date, worked } => write!(
+                f,
+                "{}: {} of work, at most {} allowed",
+                date,
+                minutes(worked),
+                minutes(&max_daily_work())
+            ),
+            Self::ShortRest { date, rest } => write!(
+                f,
+                "{}: {} of rest since the previous day, {} required",
+                date,
+                minutes(rest),
+                minutes(&min_rest())
+            ),
+        }
+    }
+}
+
+/// Work and counted breaks of a day with end of workday
+struct DayWork {
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    worked: Duration,
+    breaks: Duration,
+}
+
+impl DayWork {
+    /// None for days without work or end of workday
+    fn new(times: &[(Option<u64>, DateTime<Tz>)]) -> Option<Self> {
+        let (first, last) = (times.first()?, times.last()?);
+        if last.0.is_some() {
+            return None;
+        }
+        let mut worked = Duration::zero();
+        let mut breaks = Duration::zero();
+        for work in times.windows(2) {
+            let duration = work[1].1 - work[0].1;
+            match work[0].0 {
+                Some(_) => worked = worked + duration,
+                None if duration >= min_break() => breaks = breaks + duration,
+                None => {}
+            }
+        }
+        Some(Self {
+            start: first.1,
+            end: last.1,
+            worked,
+            breaks,
+        })
+    }
+}
+
+/// Work and the breaks that count towards the required ones, None for days without work or
+/// end of workday
+pub fn work_and_breaks(times: &[(Option<u64>, DateTime<Tz>)]) -> Option<(Duration, Duration)> {
+    DayWork::new(times).map(|day| (day.worked, day.breaks))
+}
+
+/// Work to deduct from a day because its breaks are too short. The work is reduced as if the
+/// missing break had been taken, so it never drops below the threshold that required it.
+pub fn break_deduction(times: &[(Option<u64>, DateTime<Tz>)]) -> Duration {
+    let Some(day) = DayWork::new(times) else {
+        return Duration::zero();
+    };
+    let mut cap = day.worked;
+    let rules = break_rules()
+        .into_iter()
+        .chain(std::iter::once((Duration::zero(), Duration::zero())));
+    for (threshold, required) in rules {
+        let credited = (day.worked - (required - day.breaks).max(Duration::zero())).min(cap);
+        if credited > threshold {
+            return day.worked - credited;
+        }
+        cap = threshold;
+    }
+    Duration::zero()
+}
+
+/// Violations within the work times of a day. Days without end of workday are not checked.
+pub fn check_times(date: NaiveDate, times: &[(Option<u64>, DateTime<Tz>)]) -> Vec<Violation> {
+    let Some(day) = DayWork::new(times) else {
+        return Vec::new();
+    };
+    let mut violations = Vec::new();
+    let required = required_break(day.worked);
+    if day.breaks < required {
+        violations.push(Violation::MissingBreak {
+            date,
+            worked: day.worked,
+            breaks: day.breaks,
+            required,
+        });
+    }
+    if day.worked > max_daily_work() {
+        violations.push(Violation::TooMuchWork {
+            date,
+            worked: day.worked,
+        });
+    }
+    violations
+}
+
+/// Work times of a day, with a running interval ending now on the current day
+fn closed_work_times<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<WorkTimes, Error> {
+    let mut times = db.get_work_on_date(&date)?;
+    let now = db.local_time(&db.now())?;
+    if let Some((Some(_), _)) = times.last() {
+        if now.date_naive() == date {
+            times.push((None, now));
+        }
+    }
+    Ok(times)
+}
+
+/// Violations on a day, including the rest since the previous day. On the current day a
+/// running interval counts until now.
+pub fn check_day<T: TimeProvider>(
+    db: &Database<T>,
+    date: NaiveDate,
+) -> Result<Vec<Violation>, Error> {
+    let times = closed_work_times(db, date)?;
+    let mut violations = Vec::new();
+    let previous = db.get_work_on_date(&(date - Duration::days(1)))?;
+    if let (Some(previous), Some(day)) = (DayWork::new(&previous), DayWork::new(&times)) {
+        let rest = day.start - previous.end;
+        if rest < min_rest() {
+            violations.push(Violation::ShortRest { date, rest });
+        }
+    }
+    violations.extend(check_times(date, &times));
+    Ok(violations)
+}
+
+/// Violations on the days from `start` (inclusive) to `end` (exclusive)
+pub fn check_range<T: TimeProvider>(
+    db: &Database<T>,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<Violation>, Error> {
+    let mut violations = Vec::new();
+    let mut date = start;
+    while date < end {
+        violations.extend(check_day(db, date)?);
+        date += Duration::days(1);
+    }
+    Ok(violations)
+}
+
+/// Whether missing breaks are deducted from the work time
+pub fn deducts_breaks<T: TimeProvider>(db: &Database<T>) -> Result<bool, Error> {
+    Ok(db.get_kv_optional::<bool>(DEDUCT_BREAKS)?.unwrap_or(false))
+}
+
+/// Turn the deduction of missing breaks on or off. Applies to all past days.
+pub fn set_deduct_breaks<T: TimeProvider>(db: &Database<T>, deduct: bool) -> Result<(), Error> {
+    db.set_kv(DEDUCT_BREAKS, deduct)?;
+    db.clear_daily_summaries()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        break_deduction, check_range, check_times, required_break, set_deduct_breaks, Violation,
+    };
+    use crate::business_logic::get_workday_time;
+    use crate::database::{tests::MockTime, Database, TimeProvider};
+    use chrono::{DateTime, Duration, NaiveDate, TimeZone};
+    use chrono_tz::Tz;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Tz> {
+        Tz::UTC
+            .with_ymd_and_hms(2000, 1, 1, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn breaks_and_daily_work() {
+        let date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let hours = Duration::hours;
+        let minutes = Duration::minutes;
+        assert_eq!(required_break(hours(6)), Duration::zero());
+        assert_eq!(required_break(hours(6) + minutes(1)), minutes(30));
+        assert_eq!(required_break(hours(9) + minutes(1)), minutes(45));
+
+        // 6:10 of work with two breaks of 10 minutes, which don't count
+        let short_breaks = [
+            (Some(1), at(8, 0)),
+            (None, at(10, 0)),
+            (Some(1), at(10, 10)),
+            (None, at(12, 0)),
+            (Some(1), at(12, 10)),
+            (None, at(14, 30)),
+        ];
+        assert_eq!(
+            check_times(date, &short_breaks),
+            vec![Violation::MissingBreak {
+                date,
+                worked: hours(6) + minutes(10),
+                breaks: Duration::zero(),
+                required: minutes(30),
+            }]
+        );
+        assert_eq!(break_deduction(&short_breaks), minutes(10));
+
+        // 11 hours with a break of 30 minutes
+        let long_day = [
+            (Some(1), at(7, 0)),
+            (None, at(12, 0)),
+            (Some(1), at(12, 30)),
+            (None, at(18, 30)),
+        ];
+        assert_eq!(
+            check_times(date, &long_day),
+            vec![
+                Violation::MissingBreak {
+                    date,
+                    worked: hours(11),
+                    breaks: minutes(30),
+                    required: minutes(45),
+                },
+                Violation::TooMuchWork {
+                    date,
+                    worked: hours(11),
+                },
+            ]
+        );
+        assert_eq!(break_deduction(&long_day), minutes(15));
+
+        // 9:20 without break only needs 30 minutes once reduced below 9 hours
+        let no_break = [(Some(1), at(8, 0)), (None, at(17, 20))];
+        assert_eq!(break_deduction(&no_break), minutes(30));
+        let running = [(Some(1), at(8, 0))];
+        assert!(check_times(date, &running).is_empty());
+        assert_eq!(break_deduction(&running), Duration::zero());
+    }
+
+    #[test]
+    fn rest_and_deduction() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        let monday = t.now().date_naive();
+        // Monday 9:00 to 16:00 without break, Tuesday from 2:00
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(7);
+        db.set_current_work(None).unwrap();
+        t.advance(10);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(1);
+
+        let tuesday = monday + Duration::days(1);
+        assert_eq!(
+            check_range(&db, monday, tuesday + Duration::days(1)).unwrap(),
+            vec![
+                Violation::MissingBreak {
+                    date: monday,
+                    worked: Duration::hours(7),
+                    breaks: Duration::zero(),
+                    required: Duration::minutes(30),
+                },
+                Violation::ShortRest {
+                    date: tuesday,
+                    rest: Duration::hours(10),
+                },
+            ]
+        );
+
+        let worked = |db: &Database<MockTime>| get_workday_time(db, monday).unwrap().work_done;
+        assert_eq!(worked(&db), Ok(Duration::hours(7)));
+        set_deduct_breaks(&db, true).unwrap();
+        assert_eq!(worked(&db), Ok(Duration::minutes(6 * 60 + 30)));
+    }
+}
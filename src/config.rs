@@ -0,0 +1,357 @@
+//! Settings shared by the frontends. Each value is taken from the first of: command line
+//! flags, environment variables, the TOML config file and the built-in defaults.
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, toml::de::Error),
+    InvalidValue { name: String, value: String },
+    MissingValue(String),
+    UnknownFlag(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "Cannot read {}: {}", path.display(), e),
+            Self::Toml(path, e) => write!(f, "Invalid config file {}: {}", path.display(), e),
+            Self::InvalidValue { name, value } => {
+                write!(f, "Invalid value '{}' for {}", value, name)
+            }
+            Self::MissingValue(flag) => write!(f, "Missing value for {}", flag),
+            Self::UnknownFlag(flag) => write!(
+                f,
+                "Unknown flag {}. Known flags: {}",
+                flag,
+                FLAGS.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+const FLAGS: [&str; 7] = [
+    "--config",
+    "--database",
+    "--export-dir",
+    "--idle-minutes",
+    "--bind",
+    "--port",
+    "--static-dir",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub database: PathBuf,
+    /// Where the GUI writes CSV exports
+    pub export_dir: PathBuf,
+    /// The GUI asks what to do with longer idle periods during work, 0 turns this off
+    pub idle_minutes: u32,
+    pub server: ServerConfig,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    /// Serve files from this directory under `/static`
+    pub static_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    database: Option<PathBuf>,
+    export_dir: Option<PathBuf>,
+    idle_minutes: Option<u32>,
+    #[serde(default)]
+    server: FileServerConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileServerConfig {
+    bind: Option<String>,
+    port: Option<u16>,
+    static_dir: Option<PathBuf>,
+}
+
+/// Values given on the command line or in the environment
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Overrides {
+    pub config_file: Option<PathBuf>,
+    pub database: Option<PathBuf>,
+    pub export_dir: Option<PathBuf>,
+    pub idle_minutes: Option<u32>,
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    pub static_dir: Option<PathBuf>,
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: String) -> Result<T, Error> {
+    value.parse().map_err(|_| Error::InvalidValue {
+        name: name.to_owned(),
+        value,
+    })
+}
+
+impl Overrides {
+    /// Parse the flags `--config`, `--database`, `--export-dir`, `--idle-minutes`, `--bind`,
+    /// `--port` and `--static-dir`, given either as `--flag value` or `--flag=value`
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, Error> {
+        let mut res = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_owned(), value.to_owned()),
+                None => {
+                    if !FLAGS.contains(&arg.as_str()) {
+                        return Err(Error::UnknownFlag(arg));
+                    }
+                    let value = args
+                        .next()
+                        .ok_or_else(|| Error::MissingValue(arg.clone()))?;
+                    (arg, value)
+                }
+            };
+            match flag.as_str() {
+                "--config" => res.config_file = Some(value.into()),
+                "--database" => res.database = Some(value.into()),
+                "--export-dir" => res.export_dir = Some(value.into()),
+                "--idle-minutes" => res.idle_minutes = Some(parse_number(&flag, value)?),
+                "--bind" => res.bind = Some(value),
+                "--port" => res.port = Some(parse_number(&flag, value)?),
+                "--static-dir" => res.static_dir = Some(value.into()),
+                _ => return Err(Error::UnknownFlag(flag)),
+            }
+        }
+        Ok(res)
+    }
+
+    /// Read `TIMETRAX_CONFIG`, `TIMETRAX_DATABASE`, `TIMETRAX_EXPORT_DIR`,
+    /// `TIMETRAX_IDLE_MINUTES`, `TIMETRAX_BIND`, `TIMETRAX_PORT` and `TIMETRAX_STATIC_DIR`
+    pub fn from_env<F: Fn(&str) -> Option<String>>(var: F) -> Result<Self, Error> {
+        Ok(Self {
+            config_file: var("TIMETRAX_CONFIG").map(Into::into),
+            database: var("TIMETRAX_DATABASE").map(Into::into),
+            export_dir: var("TIMETRAX_EXPORT_DIR").map(Into::into),
+            idle_minutes: var("TIMETRAX_IDLE_MINUTES")
+                .map(|minutes| parse_number("TIMETRAX_IDLE_MINUTES", minutes))
+                .transpose()?,
+            bind: var("TIMETRAX_BIND"),
+            port: var("TIMETRAX_PORT")
+                .map(|port| parse_number("TIMETRAX_PORT", port))
+                .transpose()?,
+            static_dir: var("TIMETRAX_STATIC_DIR").map(Into::into),
+        })
+    }
+
+    /// Combine with `other`, preferring the values of `self`
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            config_file: self.config_file.or(other.config_file),
+            database: self.database.or(other.database),
+            export_dir: self.export_dir.or(other.export_dir),
+            idle_minutes: self.idle_minutes.or(other.idle_minutes),
+            bind: self.bind.or(other.bind),
+            port: self.port.or(other.port),
+            static_dir: self.static_dir.or(other.static_dir),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/timetrax/config.toml` or the platform equivalent
+pub fn default_config_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("timetrax").join("config.toml"))
+}
+
+/// Database of versions before the configuration, relative to the working directory
+const LEGACY_DATABASE: &str = "work.db";
+
+/// `$XDG_DATA_HOME/timetrax/work.db` or the platform equivalent, `./work.db` if there is none
+pub fn default_database() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("timetrax").join("work.db"))
+        .unwrap_or_else(|| PathBuf::from(LEGACY_DATABASE))
+}
+
+/// Move the legacy database with its journal files to `database` unless that exists already.
+/// Returns whether it was moved.
+fn migrate_legacy_database(legacy: &Path, database: &Path) -> Result<bool, Error> {
+    if database.exists() || !legacy.is_file() || legacy == database {
+        return Ok(false);
+    }
+    for suffix in ["-wal", "-shm", ""] {
+        let mut from = legacy.as_os_str().to_owned();
+        from.push(suffix);
+        let from = PathBuf::from(from);
+        if !from.exists() {
+            continue;
+        }
+        let mut to = database.as_os_str().to_owned();
+        to.push(suffix);
+        // Renaming fails across file systems
+        std::fs::rename(&from, &to)
+            .or_else(|_| std::fs::copy(&from, &to).and_then(|_| std::fs::remove_file(&from)))
+            .map_err(|e| Error::Io(from, e))?;
+    }
+    Ok(true)
+}
+
+/// The download directory, falling back to the home directory
+pub fn default_export_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn read_config_file(path: &Path) -> Result<FileConfig, Error> {
+    let content = std::fs::read_to_string(path).map_err(|e| Error::Io(path.to_path_buf(), e))?;
+    toml::from_str(&content).map_err(|e| Error::Toml(path.to_path_buf(), e))
+}
+
+impl Config {
+    /// Load the configuration, with `args` parsed from the command line
+    pub fn load(args: Overrides) -> Result<Self, Error> {
+        let env = Overrides::from_env(|name| std::env::var(name).ok())?;
+        Self::load_with(args.or(env), default_config_file())
+    }
+
+    fn load_with(overrides: Overrides, default_file: Option<PathBuf>) -> Result<Self, Error> {
+        let file = match (&overrides.config_file, default_file) {
+            // An explicitly given file has to exist
+            (Some(path), _) => read_config_file(path)?,
+            (None, Some(path)) if path.exists() => read_config_file(&path)?,
+            (None, _) => FileConfig::default(),
+        };
+        Ok(Self {
+            database: overrides
+                .database
+                .or(file.database)
+                .unwrap_or_else(default_database),
+            export_dir: overrides
+                .export_dir
+                .or(file.export_dir)
+                .unwrap_or_else(default_export_dir),
+            idle_minutes: overrides.idle_minutes.or(file.idle_minutes).unwrap_or(5),
+            server: ServerConfig {
+                bind: overrides
+                    .bind
+                    .or(file.server.bind)
+                    .unwrap_or_else(|| "127.0.0.1".to_owned()),
+                port: overrides.port.or(file.server.port).unwrap_or(8080),
+                static_dir: overrides.static_dir.or(file.server.static_dir),
+            },
+        })
+    }
+
+    /// Create the directory containing the database if it doesn't exist yet. If the default
+    /// database is used but doesn't exist, the one of older versions in the working directory is
+    /// moved there once. Returns the path of the moved database.
+    pub fn prepare_database(&self) -> Result<Option<PathBuf>, Error> {
+        if let Some(dir) = self.database.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir).map_err(|e| Error::Io(dir.to_path_buf(), e))?;
+            }
+        }
+        let legacy = Path::new(LEGACY_DATABASE);
+        if self.database == default_database() && migrate_legacy_database(legacy, &self.database)? {
+            return Ok(Some(legacy.to_path_buf()));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate_legacy_database, Config, Error, Overrides};
+    use std::path::PathBuf;
+
+    fn args(args: &[&str]) -> Result<Overrides, Error> {
+        Overrides::from_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(args(&[]).unwrap(), Overrides::default());
+        let parsed = args(&["--database", "a.db", "--port=1234", "--bind", "0.0.0.0"]).unwrap();
+        assert_eq!(parsed.database, Some(PathBuf::from("a.db")));
+        assert_eq!(parsed.port, Some(1234));
+        assert_eq!(parsed.bind.as_deref(), Some("0.0.0.0"));
+        assert_eq!(args(&["--idle-minutes=10"]).unwrap().idle_minutes, Some(10));
+        assert!(matches!(
+            args(&["--port", "x"]),
+            Err(Error::InvalidValue { .. })
+        ));
+        assert!(matches!(args(&["--database"]), Err(Error::MissingValue(_))));
+        assert!(matches!(args(&["--foo", "x"]), Err(Error::UnknownFlag(_))));
+    }
+
+    #[test]
+    fn migrate_legacy() {
+        let dir = std::env::temp_dir().join(format!("timetrax_data_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let legacy = dir.join("legacy.db");
+        let database = dir.join("work.db");
+        assert!(!migrate_legacy_database(&legacy, &database).unwrap());
+        std::fs::write(&legacy, "data").unwrap();
+        std::fs::write(dir.join("legacy.db-wal"), "wal").unwrap();
+        assert!(migrate_legacy_database(&legacy, &database).unwrap());
+        assert!(!legacy.exists());
+        assert_eq!(std::fs::read_to_string(&database).unwrap(), "data");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("work.db-wal")).unwrap(),
+            "wal"
+        );
+        std::fs::write(&legacy, "other").unwrap();
+        assert!(
+            !migrate_legacy_database(&legacy, &database).unwrap(),
+            "Existing database is kept"
+        );
+        assert_eq!(std::fs::read_to_string(&database).unwrap(), "data");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn priorities() {
+        let path =
+            std::env::temp_dir().join(format!("timetrax_config_{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "database = \"file.db\"\n[server]\nport = 1000\nbind = \"::\"\n",
+        )
+        .unwrap();
+        let env = Overrides::from_env(|name| match name {
+            "TIMETRAX_PORT" => Some("2000".to_owned()),
+            "TIMETRAX_DATABASE" => Some("env.db".to_owned()),
+            _ => None,
+        })
+        .unwrap();
+        let flags = args(&["--database", "flag.db"]).unwrap();
+        let config = Config::load_with(flags.or(env), Some(path.clone())).unwrap();
+        assert_eq!(config.database, PathBuf::from("flag.db"));
+        assert_eq!(config.server.port, 2000);
+        assert_eq!(config.server.bind, "::");
+        assert_eq!(config.server.static_dir, None);
+
+        std::fs::write(&path, "port = 1000\n").unwrap();
+        assert!(matches!(
+            Config::load_with(Overrides::default(), Some(path.clone())),
+            Err(Error::Toml(..))
+        ));
+        std::fs::remove_file(&path).ok();
+        let defaults = Config::load_with(Overrides::default(), Some(path.clone())).unwrap();
+        assert_eq!(defaults.server.port, 8080, "Missing default file is fine");
+        let explicit = Overrides {
+            config_file: Some(path),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Config::load_with(explicit, None),
+            Err(Error::Io(..))
+        ));
+    }
+}
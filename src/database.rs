@@ -1,12 +1,474 @@
+use std::collections::HashMap;
 use std::path::Path;
 
-use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
-pub use rusqlite::Result;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use holiday_de::GermanRegion;
 use rusqlite::{Connection, OptionalExtension};
-pub type Error = rusqlite::Error;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    Sqlite(rusqlite::Error),
+    SchemaTooNew { found: usize, supported: usize },
+    NoSuchEntry(DateTime<Utc>),
+    NoSuchItem(u64),
+    DuplicateEntry(DateTime<Utc>),
+    InvalidMove(DateTime<Utc>),
+    MalformedDay(NaiveDate),
+    NoSuchUser(String),
+    InvalidParent(u64),
+    Overlap(DateTime<Utc>),
+    InvalidInterval(DateTime<Utc>),
+}
+impl From<rusqlite::Error> for Error {
+    fn from(value: rusqlite::Error) -> Self {
+        Self::Sqlite(value)
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sqlite(e) => e.fmt(f),
+            Self::SchemaTooNew { found, supported } => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}",
+                found, supported
+            ),
+            Self::NoSuchEntry(t) => write!(f, "No work entry starts at {}", t),
+            Self::NoSuchItem(id) => write!(f, "No work item with id {}", id),
+            Self::DuplicateEntry(t) => write!(f, "A work entry already starts at {}", t),
+            Self::InvalidMove(t) => write!(
+                f,
+                "Entry at {} can only be moved within its day and neighbouring entries",
+                t
+            ),
+            Self::MalformedDay(d) => write!(f, "Edit would leave {} without end of workday", d),
+            Self::NoSuchUser(name) => write!(f, "No user named {}", name),
+            Self::InvalidParent(id) => write!(
+                f,
+                "Work item {} can't be placed below itself or its sub-items",
+                id
+            ),
+            Self::Overlap(t) => write!(f, "Interval starting at {} overlaps recorded work", t),
+            Self::InvalidInterval(t) => write!(
+                f,
+                "Interval starting at {} has to end later on the same day",
+                t
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Schema upgrade steps. Entry `i` upgrades a database from version `i` to `i+1`.
+/// Steps are only ever appended, never changed once released.
+const MIGRATIONS: &[&str] = &[
+    // 1: Initial schema. Uses IF NOT EXISTS so databases created before versioning are adopted.
+    "CREATE TABLE IF NOT EXISTS work_items (id INTEGER PRIMARY KEY ASC, name TEXT NOT NULL UNIQUE, description TEXT, visible BOOLEAN NOT NULL);
+    CREATE TABLE IF NOT EXISTS work_times (start TEXT NOT NULL UNIQUE, work_item INTEGER, FOREIGN KEY (work_item) REFERENCES work_items (id));
+    CREATE TABLE IF NOT EXISTS key_value (key TEXT PRIMARY KEY, value ANY);
+    CREATE TABLE IF NOT EXISTS expected_time (date STRING PRIMARY KEY ASC, seconds INTEGER);
+    INSERT OR IGNORE INTO key_value(key, value) VALUES ('default_time', 7*60*60);",
+    // 2: Holiday region, defaulting to the previously hard-coded one. NULL means no holidays.
+    "INSERT OR IGNORE INTO key_value(key, value) VALUES ('holiday_region', 'BadenWuerttemberg');",
+    // 3: Weekly working schedules. Each one is valid until the next one starts.
+    "CREATE TABLE work_schedule (valid_from STRING PRIMARY KEY ASC, monday INTEGER NOT NULL, tuesday INTEGER NOT NULL, wednesday INTEGER NOT NULL, thursday INTEGER NOT NULL, friday INTEGER NOT NULL, saturday INTEGER NOT NULL, sunday INTEGER NOT NULL);",
+    // 4: Absences and yearly vacation entitlement in days
+    "CREATE TABLE absences (date STRING PRIMARY KEY ASC, kind TEXT NOT NULL, half_day BOOLEAN NOT NULL);
+    CREATE TABLE vacation_entitlement (year INTEGER PRIMARY KEY ASC, days REAL NOT NULL);",
+    // 5: Cached per-day results. worked is NULL for inconsistent days. Triggers drop the
+    // cached value of a day whenever something it was computed from changes.
+    "CREATE TABLE daily_summary (date STRING PRIMARY KEY ASC, worked INTEGER, expected INTEGER NOT NULL);
+    CREATE TRIGGER work_times_insert_summary AFTER INSERT ON work_times BEGIN
+        DELETE FROM daily_summary WHERE date=date(NEW.start,'localtime');
+    END;
+    CREATE TRIGGER work_times_update_summary AFTER UPDATE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE date IN (date(OLD.start,'localtime'), date(NEW.start,'localtime'));
+    END;
+    CREATE TRIGGER work_times_delete_summary AFTER DELETE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE date=date(OLD.start,'localtime');
+    END;
+    CREATE TRIGGER expected_time_insert_summary AFTER INSERT ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE date=NEW.date;
+    END;
+    CREATE TRIGGER expected_time_update_summary AFTER UPDATE ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE date IN (OLD.date, NEW.date);
+    END;
+    CREATE TRIGGER expected_time_delete_summary AFTER DELETE ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE date=OLD.date;
+    END;
+    CREATE TRIGGER absences_insert_summary AFTER INSERT ON absences BEGIN
+        DELETE FROM daily_summary WHERE date=NEW.date;
+    END;
+    CREATE TRIGGER absences_update_summary AFTER UPDATE ON absences BEGIN
+        DELETE FROM daily_summary WHERE date IN (OLD.date, NEW.date);
+    END;
+    CREATE TRIGGER absences_delete_summary AFTER DELETE ON absences BEGIN
+        DELETE FROM daily_summary WHERE date=OLD.date;
+    END;",
+    // 6: User accounts. All existing data belongs to the first user, an admin without password.
+    // Tables are rebuilt to add user_id to their keys, which drops the old triggers.
+    "CREATE TABLE users (id INTEGER PRIMARY KEY ASC, name TEXT NOT NULL UNIQUE, password_hash TEXT, admin BOOLEAN NOT NULL);
+    INSERT INTO users(id, name, password_hash, admin) VALUES (1, 'admin', NULL, 1);
+    CREATE TABLE sessions (token_hash TEXT PRIMARY KEY, user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, created TEXT NOT NULL);
+    CREATE TABLE new_work_items (id INTEGER PRIMARY KEY ASC, user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, name TEXT NOT NULL, description TEXT, visible BOOLEAN NOT NULL, UNIQUE (user_id, name));
+    INSERT INTO new_work_items SELECT id, 1, name, description, visible FROM work_items;
+    CREATE TABLE new_work_times (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, start TEXT NOT NULL, work_item INTEGER, UNIQUE (user_id, start), FOREIGN KEY (work_item) REFERENCES work_items (id));
+    INSERT INTO new_work_times SELECT 1, start, work_item FROM work_times;
+    CREATE TABLE new_key_value (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, key TEXT NOT NULL, value ANY, PRIMARY KEY (user_id, key));
+    INSERT INTO new_key_value SELECT 1, key, value FROM key_value;
+    CREATE TABLE new_expected_time (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, date STRING NOT NULL, seconds INTEGER, PRIMARY KEY (user_id, date));
+    INSERT INTO new_expected_time SELECT 1, date, seconds FROM expected_time;
+    CREATE TABLE new_work_schedule (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, valid_from STRING NOT NULL, monday INTEGER NOT NULL, tuesday INTEGER NOT NULL, wednesday INTEGER NOT NULL, thursday INTEGER NOT NULL, friday INTEGER NOT NULL, saturday INTEGER NOT NULL, sunday INTEGER NOT NULL, PRIMARY KEY (user_id, valid_from));
+    INSERT INTO new_work_schedule SELECT 1, * FROM work_schedule;
+    CREATE TABLE new_absences (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, date STRING NOT NULL, kind TEXT NOT NULL, half_day BOOLEAN NOT NULL, PRIMARY KEY (user_id, date));
+    INSERT INTO new_absences SELECT 1, * FROM absences;
+    CREATE TABLE new_vacation_entitlement (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, year INTEGER NOT NULL, days REAL NOT NULL, PRIMARY KEY (user_id, year));
+    INSERT INTO new_vacation_entitlement SELECT 1, * FROM vacation_entitlement;
+    CREATE TABLE new_daily_summary (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, date STRING NOT NULL, worked INTEGER, expected INTEGER NOT NULL, PRIMARY KEY (user_id, date));
+    INSERT INTO new_daily_summary SELECT 1, * FROM daily_summary;
+    DROP TABLE work_times;
+    DROP TABLE work_items;
+    DROP TABLE key_value;
+    DROP TABLE expected_time;
+    DROP TABLE work_schedule;
+    DROP TABLE absences;
+    DROP TABLE vacation_entitlement;
+    DROP TABLE daily_summary;
+    ALTER TABLE new_work_items RENAME TO work_items;
+    ALTER TABLE new_work_times RENAME TO work_times;
+    ALTER TABLE new_key_value RENAME TO key_value;
+    ALTER TABLE new_expected_time RENAME TO expected_time;
+    ALTER TABLE new_work_schedule RENAME TO work_schedule;
+    ALTER TABLE new_absences RENAME TO absences;
+    ALTER TABLE new_vacation_entitlement RENAME TO vacation_entitlement;
+    ALTER TABLE new_daily_summary RENAME TO daily_summary;
+    CREATE TRIGGER work_times_insert_summary AFTER INSERT ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=NEW.user_id AND date=date(NEW.start,'localtime');
+    END;
+    CREATE TRIGGER work_times_update_summary AFTER UPDATE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date IN (date(OLD.start,'localtime'), date(NEW.start,'localtime'));
+    END;
+    CREATE TRIGGER work_times_delete_summary AFTER DELETE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date=date(OLD.start,'localtime');
+    END;
+    CREATE TRIGGER expected_time_insert_summary AFTER INSERT ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE user_id=NEW.user_id AND date=NEW.date;
+    END;
+    CREATE TRIGGER expected_time_update_summary AFTER UPDATE ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date IN (OLD.date, NEW.date);
+    END;
+    CREATE TRIGGER expected_time_delete_summary AFTER DELETE ON expected_time BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date=OLD.date;
+    END;
+    CREATE TRIGGER absences_insert_summary AFTER INSERT ON absences BEGIN
+        DELETE FROM daily_summary WHERE user_id=NEW.user_id AND date=NEW.date;
+    END;
+    CREATE TRIGGER absences_update_summary AFTER UPDATE ON absences BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date IN (OLD.date, NEW.date);
+    END;
+    CREATE TRIGGER absences_delete_summary AFTER DELETE ON absences BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date=OLD.date;
+    END;",
+    // 7: Archived work items are finished for good. Unlike hidden ones they only show up in
+    // reports of their past work.
+    "ALTER TABLE work_items ADD COLUMN archived BOOLEAN NOT NULL DEFAULT 0;",
+    // 8: Work items form a tree. Names only have to be unique among siblings, so the table is
+    // rebuilt without the old constraint.
+    "CREATE TABLE new_work_items (id INTEGER PRIMARY KEY ASC, user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, parent INTEGER REFERENCES work_items (id), name TEXT NOT NULL, description TEXT, visible BOOLEAN NOT NULL, archived BOOLEAN NOT NULL DEFAULT 0);
+    INSERT INTO new_work_items SELECT id, user_id, NULL, name, description, visible, archived FROM work_items;
+    DROP TABLE work_items;
+    ALTER TABLE new_work_items RENAME TO work_items;
+    CREATE UNIQUE INDEX work_items_name ON work_items (user_id, IFNULL(parent, 0), name);",
+    // 9: Notes and tags of intervals. Tags belong to the interval starting at `start` and
+    // follow it when the switch is moved.
+    "ALTER TABLE work_times ADD COLUMN note TEXT;
+    CREATE TABLE tags (id INTEGER PRIMARY KEY ASC, user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, name TEXT NOT NULL, UNIQUE (user_id, name));
+    CREATE TABLE interval_tags (user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE, start TEXT NOT NULL, tag INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE, PRIMARY KEY (user_id, start, tag));
+    CREATE TRIGGER work_times_update_tags AFTER UPDATE OF start ON work_times BEGIN
+        UPDATE interval_tags SET start=NEW.start WHERE user_id=OLD.user_id AND start=OLD.start;
+    END;
+    CREATE TRIGGER work_times_delete_tags AFTER DELETE ON work_times BEGIN
+        DELETE FROM interval_tags WHERE user_id=OLD.user_id AND start=OLD.start;
+    END;",
+    // 10: Local day and time zone of each entry, so that days don't depend on the time zone of
+    // the process reading them. Older entries keep the day they had so far, their zone is
+    // unknown (NULL).
+    "ALTER TABLE work_times ADD COLUMN date TEXT;
+    ALTER TABLE work_times ADD COLUMN timezone TEXT;
+    DROP TRIGGER work_times_insert_summary;
+    DROP TRIGGER work_times_update_summary;
+    DROP TRIGGER work_times_delete_summary;
+    UPDATE work_times SET date=date(start,'localtime');
+    CREATE INDEX work_times_date ON work_times (user_id, date);
+    CREATE TRIGGER work_times_insert_summary AFTER INSERT ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=NEW.user_id AND date=NEW.date;
+    END;
+    CREATE TRIGGER work_times_update_summary AFTER UPDATE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date IN (OLD.date, NEW.date);
+    END;
+    CREATE TRIGGER work_times_delete_summary AFTER DELETE ON work_times BEGIN
+        DELETE FROM daily_summary WHERE user_id=OLD.user_id AND date=OLD.date;
+    END;",
+];
+
+/// Separates the names of parent and child in the path of a work item
+pub const PATH_SEPARATOR: &str = " / ";
+
+/// The user created by the migration to accounts. Owns all data of older databases and is
+/// used by the local frontends.
+pub const DEFAULT_USER: i64 = 1;
+
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+pub const HOLIDAY_REGIONS: [GermanRegion; 16] = [
+    GermanRegion::BadenWuerttemberg,
+    GermanRegion::Bayern,
+    GermanRegion::Berlin,
+    GermanRegion::Brandenburg,
+    GermanRegion::Bremen,
+    GermanRegion::Hamburg,
+    GermanRegion::Hessen,
+    GermanRegion::MechlenburgVorpommern,
+    GermanRegion::Niedersachsen,
+    GermanRegion::NordrheinWestfalen,
+    GermanRegion::RheinlandPfalz,
+    GermanRegion::Saarland,
+    GermanRegion::Sachsen,
+    GermanRegion::SachsenAnhalt,
+    GermanRegion::SchleswigHolstein,
+    GermanRegion::Thueringen,
+];
+
+/// Name under which a region is stored in the database
+pub fn holiday_region_name(region: &GermanRegion) -> &'static str {
+    match region {
+        GermanRegion::BadenWuerttemberg => "BadenWuerttemberg",
+        GermanRegion::Bayern => "Bayern",
+        GermanRegion::Berlin => "Berlin",
+        GermanRegion::Brandenburg => "Brandenburg",
+        GermanRegion::Bremen => "Bremen",
+        GermanRegion::Hamburg => "Hamburg",
+        GermanRegion::Hessen => "Hessen",
+        GermanRegion::MechlenburgVorpommern => "MecklenburgVorpommern",
+        GermanRegion::Niedersachsen => "Niedersachsen",
+        GermanRegion::NordrheinWestfalen => "NordrheinWestfalen",
+        GermanRegion::RheinlandPfalz => "RheinlandPfalz",
+        GermanRegion::Saarland => "Saarland",
+        GermanRegion::Sachsen => "Sachsen",
+        GermanRegion::SachsenAnhalt => "SachsenAnhalt",
+        GermanRegion::SchleswigHolstein => "SchleswigHolstein",
+        GermanRegion::Thueringen => "Thueringen",
+    }
+}
+
+pub fn holiday_region_from_name(name: &str) -> Option<GermanRegion> {
+    HOLIDAY_REGIONS
+        .into_iter()
+        .find(|region| holiday_region_name(region) == name)
+}
+
+fn schema_version(conn: &Connection) -> Result<usize> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    // Steps may rebuild tables, which is only possible without enforced foreign keys.
+    // Instead the keys are checked before each step is committed.
+    conn.pragma_update(None, "foreign_keys", false)?;
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(step)?;
+        let violation: Option<String> = tx
+            .query_row("PRAGMA foreign_key_check;", (), |row| row.get(0))
+            .optional()?;
+        if let Some(table) = violation {
+            return Err(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY),
+                Some(format!(
+                    "Migration {} breaks foreign keys of {}",
+                    i + 1,
+                    table
+                )),
+            )
+            .into());
+        }
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(())
+}
+
+/// Expected work per weekday, valid from a given date until the next schedule starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkSchedule {
+    pub valid_from: NaiveDate,
+    /// Seconds per weekday, starting with Monday
+    pub seconds: [i64; 7],
+}
+
+impl WorkSchedule {
+    pub fn seconds_on(&self, weekday: Weekday) -> i64 {
+        self.seconds[weekday.num_days_from_monday() as usize]
+    }
+
+    const COLUMNS: &'static str =
+        "valid_from, monday, tuesday, wednesday, thursday, friday, saturday, sunday";
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let mut seconds = [0; 7];
+        for (i, s) in seconds.iter_mut().enumerate() {
+            *s = row.get(i + 1)?;
+        }
+        Ok(Self {
+            valid_from: row.get(0)?,
+            seconds,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AbsenceKind {
+    Vacation,
+    Sick,
+    SpecialLeave,
+    /// Time off paid for with overtime. Does not reduce the expected work.
+    OvertimeCompensation,
+}
+
+impl AbsenceKind {
+    pub const ALL: [AbsenceKind; 4] = [
+        Self::Vacation,
+        Self::Sick,
+        Self::SpecialLeave,
+        Self::OvertimeCompensation,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Vacation => "vacation",
+            Self::Sick => "sick",
+            Self::SpecialLeave => "special_leave",
+            Self::OvertimeCompensation => "overtime_compensation",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+}
+
+impl std::fmt::Display for AbsenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl rusqlite::ToSql for AbsenceKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        self.name().to_sql()
+    }
+}
+
+impl rusqlite::types::FromSql for AbsenceKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        let name = value.as_str()?;
+        Self::from_name(name).ok_or_else(|| {
+            rusqlite::types::FromSqlError::Other(format!("Unknown absence {}", name).into())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Absence {
+    pub kind: AbsenceKind,
+    pub half_day: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub admin: bool,
+}
+
+impl User {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            admin: row.get(2)?,
+        })
+    }
+}
+
+/// What was done in an interval
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub note: Option<String>,
+    /// Sorted by name
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkItem {
+    pub id: u64,
+    pub parent: Option<u64>,
+    /// Name among its siblings, without the names of its parents
+    pub name: String,
+    pub description: Option<String>,
+    /// Offered in the selection of available work
+    pub visible: bool,
+    pub archived: bool,
+}
+
+impl WorkItem {
+    const COLUMNS: &'static str = "id,parent,name,description,visible,archived";
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            parent: row.get(1)?,
+            name: row.get(2)?,
+            description: row.get(3)?,
+            visible: row.get(4)?,
+            archived: row.get(5)?,
+        })
+    }
+}
 
 pub trait TimeProvider {
     fn now(&self) -> DateTime<chrono::Utc>;
+    /// Zone in which days start and end, unless the user configured one
+    fn timezone(&self) -> Tz {
+        system_timezone()
+    }
+}
+
+/// Zone of the system: `TZ` if it names a zone, otherwise the one set up for the system. Falls
+/// back to UTC.
+pub fn system_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|name| name.trim_start_matches(':').parse().ok())
+        .or_else(|| iana_time_zone::get_timezone().ok()?.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+fn parse_timezone(name: &str) -> rusqlite::Result<Tz> {
+    name.parse().map_err(|e: String| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+    })
 }
 
 impl TimeProvider for chrono::Utc {
@@ -17,74 +479,404 @@ impl TimeProvider for chrono::Utc {
 pub struct Database<'a, TP: TimeProvider> {
     conn: Connection,
     time_provider: &'a TP,
+    /// Owner of all work times, items and settings read or written
+    user: i64,
 }
 
 impl<'a, TP: TimeProvider> Database<'a, TP> {
+    /// Open and migrate the database and end a workday of [`DEFAULT_USER`] left open at the
+    /// last shutdown. This automatic end of workday is meant for the local frontends, other
+    /// accounts of the server have to end their workday themselves.
     pub fn open<P: AsRef<Path>>(path: P, time_provider: &'a TP) -> Result<Self> {
-        let conn = Connection::open(path)?;
+        let mut conn = Connection::open(path)?;
+        conn.set_db_config(
+            rusqlite::config::DbConfig::SQLITE_DBCONFIG_ENABLE_FKEY,
+            true,
+        )?;
+        // Allow other processes (e.g. CLI and GUI) to use the file at the same time
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        // Migrate before constructing the Database, so that Drop never writes into
+        // a database we refused to open.
+        migrate(&mut conn)?;
         let s = Database {
             conn,
             time_provider,
+            user: DEFAULT_USER,
         };
-        s.conn.set_db_config(
-            rusqlite::config::DbConfig::SQLITE_DBCONFIG_ENABLE_FKEY,
-            true,
-        )?;
 
-        s.create_default_entries()?;
         s.add_work_end_at_shutdown()?;
 
         Ok(s)
     }
 
-    pub fn get_kv<T: rusqlite::types::FromSql>(&self, key: &str) -> Result<T> {
-        self.conn
-            .query_row("SELECT value FROM key_value WHERE key=?;", [key], |row| {
-                row.get(0)
-            })
+    /// Work with the data of another user from now on
+    pub fn set_user(&mut self, user: i64) {
+        self.user = user;
     }
-
-    fn create_default_entries(&self) -> Result<()> {
-        self.conn.execute("CREATE TABLE IF NOT EXISTS work_items (id INTEGER PRIMARY KEY ASC, name TEXT NOT NULL UNIQUE, description TEXT, visible BOOLEAN NOT NULL);", ())?;
-        self.conn.execute("CREATE TABLE IF NOT EXISTS work_times (start TEXT NOT NULL UNIQUE, work_item INTEGER, FOREIGN KEY (work_item) REFERENCES work_items (id));", ())?;
+    pub fn user(&self) -> i64 {
+        self.user
+    }
+    /// Create a user with the default settings. Without password hash the user can't log in.
+    pub fn create_user(&self, name: &str, password_hash: Option<&str>, admin: bool) -> Result<i64> {
+        let tx = self.conn.unchecked_transaction()?;
         self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS key_value (key TEXT PRIMARY KEY, value ANY);",
-            (),
+            "INSERT INTO users(name, password_hash, admin) VALUES (?, ?, ?);",
+            (name, password_hash, admin),
         )?;
-        self.conn.execute("CREATE TABLE IF NOT EXISTS expected_time (date STRING PRIMARY KEY ASC, seconds INTEGER);", ())?;
+        let id = self.conn.last_insert_rowid();
         self.conn.execute(
-            "INSERT OR IGNORE INTO key_value(key, value) VALUES ('default_time', 7*60*60);",
-            (),
+            "INSERT INTO key_value(user_id, key, value) VALUES (?1, 'default_time', 7*60*60), (?1, 'holiday_region', 'BadenWuerttemberg');",
+            (id,),
         )?;
+        tx.commit()?;
+        Ok(id)
+    }
+    /// Delete a user with all their data
+    pub fn delete_user(&self, id: i64) -> Result<()> {
+        if self.conn.execute("DELETE FROM users WHERE id=?;", (id,))? == 0 {
+            return Err(Error::NoSuchUser(id.to_string()));
+        }
+        Ok(())
+    }
+    pub fn get_users(&self) -> Result<Vec<User>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, admin FROM users ORDER BY id ASC;")?;
+        let res = stmt.query_map((), User::from_row)?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// The user and their password hash
+    pub fn get_user_by_name(&self, name: &str) -> Result<(User, Option<String>)> {
+        self.conn
+            .query_row(
+                "SELECT id, name, admin, password_hash FROM users WHERE name=?;",
+                (name,),
+                |row| Ok((User::from_row(row)?, row.get(3)?)),
+            )
+            .optional()?
+            .ok_or_else(|| Error::NoSuchUser(name.to_owned()))
+    }
+    pub fn set_password_hash(&self, id: i64, password_hash: &str) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE users SET password_hash=? WHERE id=?;",
+            (password_hash, id),
+        )?;
+        if updated == 0 {
+            return Err(Error::NoSuchUser(id.to_string()));
+        }
+        Ok(())
+    }
+    pub fn create_session(&self, token_hash: &str, user: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO sessions(token_hash, user_id, created) VALUES (?, ?, ?);",
+            (token_hash, user, self.time_provider.now()),
+        )?;
+        Ok(())
+    }
+    /// User of a session created at or after `valid_since`
+    pub fn get_session_user(
+        &self,
+        token_hash: &str,
+        valid_since: DateTime<Utc>,
+    ) -> Result<Option<User>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT users.id, users.name, users.admin FROM sessions JOIN users ON users.id=sessions.user_id WHERE token_hash=? AND created>=?;",
+                (token_hash, valid_since),
+                User::from_row,
+            )
+            .optional()?)
+    }
+    pub fn delete_session(&self, token_hash: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM sessions WHERE token_hash=?;", (token_hash,))?;
         Ok(())
     }
+    pub fn get_kv<T: rusqlite::types::FromSql>(&self, key: &str) -> Result<T> {
+        Ok(self.conn.query_row(
+            "SELECT value FROM key_value WHERE user_id=? AND key=?;",
+            (self.user, key),
+            |row| row.get(0),
+        )?)
+    }
+    pub fn get_kv_optional<T: rusqlite::types::FromSql>(&self, key: &str) -> Result<Option<T>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT value FROM key_value WHERE user_id=? AND key=?;",
+                (self.user, key),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+    pub fn set_kv<T: rusqlite::ToSql>(&self, key: &str, value: T) -> Result<()> {
+        self.conn.execute("INSERT INTO key_value(user_id, key, value) VALUES (?, ?, ?) ON CONFLICT DO UPDATE SET value=excluded.value;", (self.user, key, value))?;
+        Ok(())
+    }
+    pub fn get_holiday_region(&self) -> Result<Option<GermanRegion>> {
+        let name = self
+            .get_kv_optional::<Option<String>>("holiday_region")?
+            .flatten();
+        name.map(|name| {
+            holiday_region_from_name(&name).ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    format!("Unknown holiday region {}", name).into(),
+                )
+                .into()
+            })
+        })
+        .transpose()
+    }
+    /// Change the region and recompute the stored expected times from today on
+    pub fn set_holiday_region(&self, region: Option<&GermanRegion>) -> Result<()> {
+        self.with_savepoint(true, || {
+            self.set_kv("holiday_region", region.map(holiday_region_name))?;
+            self.recompute_expected_time_from(self.today()?)
+        })
+    }
+    pub fn schema_version(&self) -> Result<usize> {
+        schema_version(&self.conn)
+    }
+    /// Zone in which days start and end: the one configured by the user, otherwise that of the
+    /// time provider
+    pub fn timezone(&self) -> Result<Tz> {
+        Ok(self
+            .get_configured_timezone()?
+            .unwrap_or_else(|| self.time_provider.timezone()))
+    }
+    /// Configure the zone of the user, e.g. when travelling. Recorded days keep the zone they
+    /// were recorded in. None follows the time provider.
+    pub fn set_timezone(&self, zone: Option<Tz>) -> Result<()> {
+        self.set_kv("timezone", zone.map(|zone| zone.name()))
+    }
+    /// Zone set explicitly by the user
+    pub fn get_configured_timezone(&self) -> Result<Option<Tz>> {
+        Ok(self
+            .get_kv_optional::<Option<String>>("timezone")?
+            .flatten()
+            .map(|name| parse_timezone(&name))
+            .transpose()?)
+    }
+    pub fn local_time(&self, time: &DateTime<Utc>) -> Result<DateTime<Tz>> {
+        Ok(time.with_timezone(&self.timezone()?))
+    }
+    pub fn local_date(&self, time: &DateTime<Utc>) -> Result<NaiveDate> {
+        Ok(self.local_time(time)?.date_naive())
+    }
+    pub fn today(&self) -> Result<NaiveDate> {
+        self.local_date(&self.time_provider.now())
+    }
+    /// First moment of a day. If midnight is skipped by a change of the clocks the day starts
+    /// when they were changed.
+    pub fn day_start(&self, date: NaiveDate) -> Result<DateTime<Tz>> {
+        let zone = self.timezone()?;
+        let mut time = date.and_time(NaiveTime::MIN);
+        loop {
+            if let Some(start) = zone.from_local_datetime(&time).earliest() {
+                return Ok(start);
+            }
+            time += Duration::minutes(15);
+        }
+    }
     fn add_work_end_at_shutdown(&self) -> Result<()> {
         // Check if time of last shutdown was yesterday or earlier. Then add shutdown time as end of workday if no end was inserted before
-        let last_shutdown: Option<String> = self.conn.query_row("SELECT value FROM key_value WHERE key='shutdown' AND date(?,'localtime')>date(value,'localtime');",(self.time_provider.now(),),|row| row.get(0),).optional().unwrap();
-        if let Some(shutdown_time) = last_shutdown {
-            let last_work:Option<u64>=self.conn.query_row("SELECT work_item FROM work_times WHERE date(start,'localtime')=date(?,'localtime') ORDER BY start DESC LIMIT 1", [&shutdown_time], |row| row.get(0)).optional().unwrap().flatten();
-            if last_work.is_some() {
-                self.conn.execute(
-                    "INSERT INTO work_times (start,work_item) VALUES (?,NULL)",
-                    [&shutdown_time],
-                )?;
+        let Some(shutdown) = self.get_kv_optional::<DateTime<Utc>>("shutdown")? else {
+            return Ok(());
+        };
+        let date = self.local_date(&shutdown)?;
+        if date < self.today()? {
+            if let Some((Some(_), _)) = self.get_work_on_date(&date)?.last() {
+                self.insert_entry(&shutdown, None, false)?;
             }
         }
         Ok(())
     }
     pub fn set_expected_time(&self, date: NaiveDate, time_s: i64) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO expected_time(date, seconds) VALUES (?, ?) ON CONFLICT DO UPDATE SET seconds=excluded.seconds;",
-            (&date, &time_s),
+            "INSERT INTO expected_time(user_id, date, seconds) VALUES (?, ?, ?) ON CONFLICT DO UPDATE SET seconds=excluded.seconds;",
+            (self.user, &date, &time_s),
         )?;
         Ok(())
     }
+    /// Add a schedule or replace the one starting on the same day. Stored expected times from
+    /// the start of the schedule on are recomputed, but not those before today.
+    pub fn set_work_schedule(&self, schedule: &WorkSchedule) -> Result<()> {
+        let s = &schedule.seconds;
+        self.with_savepoint(true, || {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO work_schedule(user_id, valid_from, monday, tuesday, wednesday, thursday, friday, saturday, sunday) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+                (self.user, &schedule.valid_from, s[0], s[1], s[2], s[3], s[4], s[5], s[6]),
+            )?;
+            self.recompute_expected_time_from(schedule.valid_from)
+        })
+    }
+    pub fn remove_work_schedule(&self, valid_from: NaiveDate) -> Result<()> {
+        self.with_savepoint(true, || {
+            self.conn.execute(
+                "DELETE FROM work_schedule WHERE user_id=? AND valid_from=?;",
+                (self.user, &valid_from),
+            )?;
+            self.recompute_expected_time_from(valid_from)
+        })
+    }
+    /// Recompute stored expected times from `start` on, but never those of past days
+    fn recompute_expected_time_from(&self, start: NaiveDate) -> Result<()> {
+        crate::business_logic::recompute_expected_time(self, start.max(self.today()?))
+    }
+    /// Schedule in effect on the given date, if any
+    pub fn get_work_schedule(&self, date: NaiveDate) -> Result<Option<WorkSchedule>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM work_schedule WHERE user_id=? AND valid_from<=? ORDER BY valid_from DESC LIMIT 1;",
+                    WorkSchedule::COLUMNS
+                ),
+                (self.user, &date),
+                WorkSchedule::from_row,
+            )
+            .optional()?)
+    }
+    pub fn get_work_schedules(&self) -> Result<Vec<WorkSchedule>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM work_schedule WHERE user_id=? ORDER BY valid_from ASC;",
+            WorkSchedule::COLUMNS
+        ))?;
+        let res = stmt.query_map((self.user,), WorkSchedule::from_row)?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// Set or clear (with `None`) the absence on a day
+    pub fn set_absence(&self, date: NaiveDate, absence: Option<&Absence>) -> Result<()> {
+        if let Some(absence) = absence {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO absences(user_id, date, kind, half_day) VALUES (?, ?, ?, ?);",
+                (self.user, &date, &absence.kind, &absence.half_day),
+            )?;
+        } else {
+            self.conn.execute(
+                "DELETE FROM absences WHERE user_id=? AND date=?;",
+                (self.user, &date),
+            )?;
+        }
+        Ok(())
+    }
+    pub fn get_absence(&self, date: NaiveDate) -> Result<Option<Absence>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT kind, half_day FROM absences WHERE user_id=? AND date=?;",
+                (self.user, &date),
+                |row| {
+                    Ok(Absence {
+                        kind: row.get(0)?,
+                        half_day: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+    /// Absences from `start` (inclusive) to `end` (exclusive)
+    pub fn get_absences(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, Absence)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date, kind, half_day FROM absences WHERE user_id=? AND date>=? AND date<? ORDER BY date ASC;",
+        )?;
+        let res = stmt.query_map((self.user, &start, &end), |row| {
+            Ok((
+                row.get(0)?,
+                Absence {
+                    kind: row.get(1)?,
+                    half_day: row.get(2)?,
+                },
+            ))
+        })?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    pub fn set_vacation_entitlement(&self, year: i32, days: f64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO vacation_entitlement(user_id, year, days) VALUES (?, ?, ?);",
+            (self.user, year, days),
+        )?;
+        Ok(())
+    }
+    pub fn get_vacation_entitlement(&self, year: i32) -> Result<Option<f64>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT days FROM vacation_entitlement WHERE user_id=? AND year=?;",
+                (self.user, year),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+    /// Cache the result of a day. `worked` is `None` if the day is inconsistent.
+    pub fn set_daily_summary(
+        &self,
+        date: NaiveDate,
+        worked: Option<Duration>,
+        expected: Duration,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO daily_summary(user_id, date, worked, expected) VALUES (?, ?, ?, ?);",
+            (
+                self.user,
+                &date,
+                worked.map(|w| w.num_seconds()),
+                expected.num_seconds(),
+            ),
+        )?;
+        Ok(())
+    }
+    /// Days from `start` (inclusive) to `end` (exclusive) with a cached result
+    pub fn get_summarized_dates(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT date FROM daily_summary WHERE user_id=? AND date>=? AND date<?;")?;
+        let res = stmt.query_map((self.user, &start, &end), |row| row.get(0))?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// Sum of worked minus expected time of all cached consistent days before `end`
+    pub fn get_summary_balance(&self, end: NaiveDate) -> Result<Duration> {
+        Ok(Duration::seconds(self.conn.query_row(
+            "SELECT IFNULL(SUM(worked-expected),0) FROM daily_summary WHERE user_id=? AND worked IS NOT NULL AND date<?;",
+            (self.user, &end),
+            |row| row.get(0),
+        )?))
+    }
+    /// Cached days before `end` without end of workday
+    pub fn get_summary_inconsistent_days(&self, end: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date FROM daily_summary WHERE user_id=? AND worked IS NULL AND date<? ORDER BY date ASC;",
+        )?;
+        let res = stmt.query_map((self.user, &end), |row| row.get(0))?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    pub fn clear_daily_summaries(&self) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM daily_summary WHERE user_id=?;", (self.user,))?;
+        Ok(())
+    }
+    /// Days from `start` on with a stored expected time, including future ones
+    pub fn get_expected_time_dates(&self, start: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date FROM expected_time WHERE user_id=? AND date(\"date\")>=date(?) ORDER BY date ASC;",
+        )?;
+        let res = stmt.query_map((self.user, &start), |row| row.get(0))?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
     pub fn get_expected_work(&self, date: NaiveDate) -> Result<Option<Duration>> {
         Ok(self
             .conn
             .query_row(
-                "SELECT seconds FROM expected_time WHERE date(\"date\")=date(?)",
-                (date,),
+                "SELECT seconds FROM expected_time WHERE user_id=? AND date(\"date\")=date(?)",
+                (self.user, date),
                 |row| row.get(0),
             )
             .optional()?
@@ -95,81 +887,555 @@ impl<'a, TP: TimeProvider> Database<'a, TP> {
         self.time_provider.now()
     }
     pub fn add_work_item(&self, name: &str) -> Result<usize> {
-        self.conn.execute(
-            "INSERT OR IGNORE INTO work_items(name, description, visible) VALUES (?,NULL,1);",
-            [name],
-        )
+        self.add_sub_item(None, name)
     }
-    pub fn shutdown(&self) -> Result<()> {
-        self.conn.execute("INSERT INTO key_value(key, value) VALUES ('shutdown', ?) ON CONFLICT DO UPDATE SET value=excluded.value;", (self.time_provider.now(),))?;
+    /// Add a work item below `parent`. Returns 0 if a sibling has the same name already.
+    pub fn add_sub_item(&self, parent: Option<u64>, name: &str) -> Result<usize> {
+        self.check_item(parent)?;
+        Ok(self.conn.execute(
+            "INSERT OR IGNORE INTO work_items(user_id, parent, name, description, visible) VALUES (?,?,?,NULL,1);",
+            (self.user, parent, name),
+        )?)
+    }
+    /// Move a work item with all its sub-items below `parent`, or to the top with `None`
+    pub fn set_work_item_parent(&self, id: u64, parent: Option<u64>) -> Result<()> {
+        self.check_item(parent)?;
+        let mut ancestor = parent;
+        while let Some(current) = ancestor {
+            if current == id {
+                return Err(Error::InvalidParent(id));
+            }
+            ancestor = self.conn.query_row(
+                "SELECT parent FROM work_items WHERE id=?;",
+                (current,),
+                |row| row.get(0),
+            )?;
+        }
+        self.update_work_item(id, "parent", parent)
+    }
+    pub fn rename_work_item(&self, id: u64, name: &str) -> Result<()> {
+        self.update_work_item(id, "name", name)
+    }
+    fn update_work_item<V: rusqlite::ToSql>(&self, id: u64, column: &str, value: V) -> Result<()> {
+        let updated = self.conn.execute(
+            &format!(
+                "UPDATE work_items SET {}=? WHERE id=? AND user_id=?;",
+                column
+            ),
+            (value, id, self.user),
+        )?;
+        if updated == 0 {
+            return Err(Error::NoSuchItem(id));
+        }
         Ok(())
     }
+    /// Show or hide a work item in the selection of available work
+    pub fn set_work_item_visible(&self, id: u64, visible: bool) -> Result<()> {
+        self.update_work_item(id, "visible", visible)
+    }
+    /// Set or with `None` remove the description of a work item
+    pub fn set_work_item_description(&self, id: u64, description: Option<&str>) -> Result<()> {
+        self.update_work_item(id, "description", description)
+    }
+    /// Archive a finished work item or restore it. Archived items are never available for work.
+    pub fn set_work_item_archived(&self, id: u64, archived: bool) -> Result<()> {
+        self.update_work_item(id, "archived", archived)
+    }
+    /// All work items including hidden and archived ones, ordered by name
+    pub fn get_work_items(&self) -> Result<Vec<WorkItem>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM work_items WHERE user_id=? ORDER BY name",
+            WorkItem::COLUMNS
+        ))?;
+        let res = stmt.query_map((self.user,), WorkItem::from_row)?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// Remember the time of shutdown for the selected user, see [`Database::open`]
+    pub fn shutdown(&self) -> Result<()> {
+        self.set_kv("shutdown", self.time_provider.now())
+    }
+    /// Paths and ids of the items offered for work
     pub fn get_available_work(&self) -> Result<Vec<(String, u64)>> {
+        let mut paths = self.get_work_item_names()?;
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM work_items WHERE user_id=? AND visible=1 AND archived=0 ORDER BY id",
+        )?;
+        let res = stmt.query_map((self.user,), |row| row.get(0))?;
+        res.map(|id| {
+            let id = id?;
+            Ok((paths.remove(&id).unwrap_or_default(), id))
+        })
+        .collect()
+    }
+    /// Paths of all work items including hidden and archived ones, like `Project / Task`
+    pub fn get_work_item_names(&self) -> Result<HashMap<u64, String>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT name,id FROM work_items WHERE visible=1")?;
-        let res = stmt.query_map((), |row| Ok((row.get(0)?, row.get(1)?)))?;
-        res.collect()
+            .prepare("SELECT id,parent,name FROM work_items WHERE user_id=?")?;
+        let items: HashMap<u64, (Option<u64>, String)> = stmt
+            .query_map((self.user,), |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(items
+            .iter()
+            .map(|(id, (mut parent, name))| {
+                let mut path = name.clone();
+                while let Some((grandparent, parent_name)) = parent.and_then(|p| items.get(&p)) {
+                    path = format!("{}{}{}", parent_name, PATH_SEPARATOR, path);
+                    parent = *grandparent;
+                }
+                (*id, path)
+            })
+            .collect())
     }
     pub fn get_current_work(&self) -> Result<Option<u64>> {
-        self.conn.query_row("SELECT work_item FROM work_times WHERE date(start,'localtime')=date(?,'localtime') ORDER BY start DESC LIMIT 1", (self.time_provider.now(),), |row| row.get(0)).optional().map(|x| x.flatten())
+        Ok(self.conn.query_row("SELECT work_item FROM work_times WHERE user_id=? AND date=? ORDER BY start DESC LIMIT 1", (self.user, self.today()?), |row| row.get(0)).optional().map(|x| x.flatten())?)
     }
     pub fn get_start_day(&self) -> Result<Option<chrono::NaiveDate>> {
-        self.conn
+        Ok(self
+            .conn
             .query_row(
-                "SELECT date(start,'localtime') FROM work_times ORDER BY start ASC LIMIT 1",
-                (),
+                "SELECT date FROM work_times WHERE user_id=? ORDER BY date ASC LIMIT 1",
+                (self.user,),
                 |row| row.get(0),
             )
-            .optional()
+            .optional()?)
     }
     pub fn set_current_work(&self, work_item: Option<u64>) -> Result<()> {
-        self.conn.execute("INSERT INTO work_times (start,work_item) VALUES (?,?) ON CONFLICT DO UPDATE SET work_item=excluded.work_item;", (self.time_provider.now(),work_item))?;
+        self.check_item(work_item)?;
+        self.insert_entry(&self.time_provider.now(), work_item, true)
+    }
+    /// Add an entry in the current zone. An existing entry at the same time keeps its zone and
+    /// gets the new work item if `replace` is set, otherwise it is left alone.
+    fn insert_entry(
+        &self,
+        at: &DateTime<Utc>,
+        work_item: Option<u64>,
+        replace: bool,
+    ) -> Result<()> {
+        let zone = self.timezone()?;
+        let conflict = if replace {
+            "DO UPDATE SET work_item=excluded.work_item"
+        } else {
+            "DO NOTHING"
+        };
+        self.conn.execute(
+            &format!("INSERT INTO work_times (user_id,start,work_item,date,timezone) VALUES (?,?,?,?,?) ON CONFLICT {};", conflict),
+            (self.user, at, work_item, at.with_timezone(&zone).date_naive(), zone.name()),
+        )?;
+        Ok(())
+    }
+    /// Work items of other users don't exist for this one
+    fn check_item(&self, work_item: Option<u64>) -> Result<()> {
+        if let Some(id) = work_item {
+            let owned: bool = self.conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM work_items WHERE id=? AND user_id=?);",
+                (id, self.user),
+                |row| row.get(0),
+            )?;
+            if !owned {
+                return Err(Error::NoSuchItem(id));
+            }
+        }
         Ok(())
     }
     pub fn get_work_on_date(
         &self,
         date: &chrono::NaiveDate,
-    ) -> Result<Vec<(Option<u64>, DateTime<Local>)>> {
-        let mut stmt=self.conn.prepare("SELECT work_item,start FROM work_times WHERE date(start,'localtime')=date(?) ORDER BY start ASC;")?;
-        let res = stmt.query_map((date,), |row| Ok((row.get(0)?, row.get(1)?)))?;
-        res.collect()
+    ) -> Result<Vec<(Option<u64>, DateTime<Tz>)>> {
+        let current = self.timezone()?;
+        let mut stmt=self.conn.prepare("SELECT work_item,start,timezone FROM work_times WHERE user_id=? AND date=? ORDER BY start ASC;")?;
+        let res = stmt.query_map((self.user, date), |row| {
+            let start: DateTime<Utc> = row.get(1)?;
+            let zone = match row.get::<_, Option<String>>(2)? {
+                Some(name) => parse_timezone(&name)?,
+                None => current,
+            };
+            Ok((row.get(0)?, start.with_timezone(&zone)))
+        })?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// Day and zone of the entry at `start`. Entries without zone are in the current one.
+    fn get_entry_day(&self, start: &DateTime<Utc>) -> Result<(NaiveDate, Tz)> {
+        let (date, zone): (NaiveDate, Option<String>) = self
+            .conn
+            .query_row(
+                "SELECT date,timezone FROM work_times WHERE user_id=? AND start=?;",
+                (self.user, start),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or(Error::NoSuchEntry(*start))?;
+        match zone {
+            Some(name) => Ok((date, parse_timezone(&name)?)),
+            None => Ok((date, self.timezone()?)),
+        }
+    }
+    fn get_work_entry(&self, start: &DateTime<Utc>) -> Result<Option<Option<u64>>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT work_item FROM work_times WHERE user_id=? AND start=?;",
+                (self.user, start),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+    /// Remove entries that don't change the current work, including breaks at the start of the day
+    fn normalize_day(&self, date: NaiveDate) -> Result<()> {
+        let mut previous = None;
+        // Start of the interval that continues, None for the leading breaks
+        let mut kept = None;
+        for (work_item, start) in self.get_work_on_date(&date)? {
+            let start = start.with_timezone(&Utc);
+            if work_item == previous {
+                if let Some(kept) = &kept {
+                    self.merge_annotation(&start, kept)?;
+                }
+                self.conn.execute(
+                    "DELETE FROM work_times WHERE user_id=? AND start=?;",
+                    (self.user, &start),
+                )?;
+            } else {
+                previous = work_item;
+                kept = Some(start);
+            }
+        }
+        Ok(())
+    }
+    /// Add the note and tags of the interval at `from` to those of the interval at `to`, so
+    /// they aren't lost when the intervals are merged
+    fn merge_annotation(&self, from: &DateTime<Utc>, to: &DateTime<Utc>) -> Result<()> {
+        let note: Option<String> = self.conn.query_row(
+            "SELECT note FROM work_times WHERE user_id=? AND start=?;",
+            (self.user, from),
+            |row| row.get(0),
+        )?;
+        self.conn.execute(
+            "UPDATE work_times SET note=CASE WHEN note IS NULL THEN ?3 WHEN ?3 IS NULL THEN note ELSE note||'; '||?3 END WHERE user_id=?1 AND start=?2;",
+            (self.user, to, note),
+        )?;
+        self.conn.execute(
+            "INSERT OR IGNORE INTO interval_tags (user_id,start,tag) SELECT user_id,?3,tag FROM interval_tags WHERE user_id=?1 AND start=?2;",
+            (self.user, from, to),
+        )?;
+        Ok(())
+    }
+    /// A finished day has to end with a NULL entry. Today may still be running.
+    fn check_day(&self, date: NaiveDate) -> Result<()> {
+        if date >= self.today()? {
+            return Ok(());
+        }
+        if let Some((Some(_), _)) = self.get_work_on_date(&date)?.last() {
+            return Err(Error::MalformedDay(date));
+        }
+        Ok(())
+    }
+    /// Run `f` in a savepoint, which may be nested. Its changes are rolled back if it fails or
+    /// `commit` is false.
+    pub fn with_savepoint<T, E, F>(&self, commit: bool, f: F) -> std::result::Result<T, E>
+    where
+        E: From<Error>,
+        F: FnOnce() -> std::result::Result<T, E>,
+    {
+        self.conn
+            .execute_batch("SAVEPOINT changes;")
+            .map_err(Error::from)?;
+        let res = f();
+        let end = if commit && res.is_ok() {
+            "RELEASE changes;"
+        } else {
+            "ROLLBACK TO changes; RELEASE changes;"
+        };
+        self.conn.execute_batch(end).map_err(Error::from)?;
+        res
+    }
+    /// Run an edit of the work times of a day in a savepoint. Rolled back if the day is malformed afterwards.
+    fn edit_day<F: FnOnce() -> Result<()>>(&self, date: NaiveDate, edit: F) -> Result<()> {
+        self.with_savepoint(true, || {
+            edit()?;
+            self.normalize_day(date)?;
+            self.check_day(date)
+        })
+    }
+    /// Switch to `work_item` at an arbitrary time. `None` starts a break or ends the day.
+    pub fn insert_work_switch(&self, at: DateTime<Utc>, work_item: Option<u64>) -> Result<()> {
+        self.check_item(work_item)?;
+        self.edit_day(self.local_date(&at)?, || {
+            if self.get_work_entry(&at)?.is_some() {
+                return Err(Error::DuplicateEntry(at));
+            }
+            self.insert_entry(&at, work_item, false)
+        })
+    }
+    /// Record work on `work_item` from `start` to `end` during a break of a single day. Without
+    /// `end` the work is still running.
+    pub fn insert_interval(
+        &self,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        work_item: u64,
+    ) -> Result<()> {
+        self.check_item(Some(work_item))?;
+        let date = self.local_date(&start)?;
+        if let Some(end) = end {
+            if end <= start || self.local_date(&end)? != date {
+                return Err(Error::InvalidInterval(start));
+            }
+        }
+        self.edit_day(date, || {
+            let working = self
+                .get_work_on_date(&date)?
+                .into_iter()
+                .rev()
+                .find(|(_, t)| *t <= start)
+                .and_then(|(work_item, _)| work_item)
+                .is_some();
+            let later: i64 = self.conn.query_row(
+                "SELECT count(*) FROM work_times WHERE user_id=? AND start>? AND (?3 IS NULL OR start<?3);",
+                (self.user, &start, end),
+                |row| row.get(0),
+            )?;
+            if working || later > 0 {
+                return Err(Error::Overlap(start));
+            }
+            self.insert_entry(&start, Some(work_item), true)?;
+            if let Some(end) = end {
+                // Work following directly keeps its entry
+                self.insert_entry(&end, None, false)?;
+            }
+            Ok(())
+        })
+    }
+    /// Record `work_item` (or a break) from `start` until the end of its day, replacing the
+    /// later switches. The day is finished afterwards.
+    pub fn set_work_until_day_end(
+        &self,
+        start: DateTime<Utc>,
+        work_item: Option<u64>,
+    ) -> Result<()> {
+        self.check_item(work_item)?;
+        let date = self.local_date(&start)?;
+        let end = self
+            .day_start(date + Duration::days(1))?
+            .with_timezone(&Utc)
+            - Duration::seconds(1);
+        self.edit_day(date, || {
+            self.conn.execute(
+                "DELETE FROM work_times WHERE user_id=? AND date=? AND start>?;",
+                (self.user, &date, &start),
+            )?;
+            self.insert_entry(&start, work_item, true)?;
+            if work_item.is_some() {
+                self.insert_entry(&end, None, true)?;
+            }
+            Ok(())
+        })
+    }
+    /// Record `work_item` (or a break) from `start` to `end` on one day, replacing the switches
+    /// in between. The work running at `end` continues afterwards.
+    pub fn set_work_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        work_item: Option<u64>,
+    ) -> Result<()> {
+        self.check_item(work_item)?;
+        let date = self.local_date(&start)?;
+        if end <= start || self.local_date(&end)? != date {
+            return Err(Error::InvalidInterval(start));
+        }
+        self.edit_day(date, || {
+            let after = self
+                .get_work_on_date(&date)?
+                .into_iter()
+                .rev()
+                .find(|(_, t)| *t <= end)
+                .and_then(|(work_item, _)| work_item);
+            self.conn.execute(
+                "DELETE FROM work_times WHERE user_id=? AND start>? AND start<=?;",
+                (self.user, &start, &end),
+            )?;
+            for (at, work_item) in [(&start, work_item), (&end, after)] {
+                self.insert_entry(at, work_item, true)?;
+            }
+            Ok(())
+        })
+    }
+    /// Move the boundary at `from` to `to`. It may not pass other entries or leave its day.
+    pub fn move_work_switch(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<()> {
+        let (date, zone) = self.get_entry_day(&from)?;
+        self.edit_day(date, || {
+            let (low, high) = if from < to { (from, to) } else { (to, from) };
+            let passed: i64 = self.conn.query_row(
+                "SELECT count(*) FROM work_times WHERE user_id=? AND start>=? AND start<=? AND start<>?;",
+                (self.user, &low, &high, &from),
+                |row| row.get(0),
+            )?;
+            if passed > 0 || to.with_timezone(&zone).date_naive() != date {
+                return Err(Error::InvalidMove(from));
+            }
+            self.conn.execute(
+                "UPDATE work_times SET start=? WHERE user_id=? AND start=?;",
+                (&to, self.user, &from),
+            )?;
+            Ok(())
+        })
+    }
+    /// Change the work item of the interval starting at `start`
+    pub fn set_interval_work(&self, start: DateTime<Utc>, work_item: Option<u64>) -> Result<()> {
+        self.check_item(work_item)?;
+        let (date, _) = self.get_entry_day(&start)?;
+        self.edit_day(date, || {
+            let updated = self.conn.execute(
+                "UPDATE work_times SET work_item=? WHERE user_id=? AND start=?;",
+                (work_item, self.user, &start),
+            )?;
+            if updated == 0 {
+                return Err(Error::NoSuchEntry(start));
+            }
+            Ok(())
+        })
+    }
+    /// Turn the interval starting at `start` into a break
+    pub fn delete_interval(&self, start: DateTime<Utc>) -> Result<()> {
+        self.set_interval_work(start, None)
+    }
+    /// Start of the interval with work that is running now
+    pub fn get_current_interval(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(match self.get_work_on_date(&self.today()?)?.last() {
+            Some((Some(_), start)) => Some(start.with_timezone(&Utc)),
+            _ => None,
+        })
+    }
+    /// Set or remove the note of the interval starting at `start`
+    pub fn set_interval_note(&self, start: DateTime<Utc>, note: Option<&str>) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE work_times SET note=? WHERE user_id=? AND start=?;",
+            (note, self.user, &start),
+        )?;
+        if updated == 0 {
+            return Err(Error::NoSuchEntry(start));
+        }
+        Ok(())
+    }
+    /// Replace the tags of the interval starting at `start`. Unknown tags are created.
+    pub fn set_interval_tags(&self, start: DateTime<Utc>, tags: &[String]) -> Result<()> {
+        if self.get_work_entry(&start)?.is_none() {
+            return Err(Error::NoSuchEntry(start));
+        }
+        self.with_savepoint(true, || {
+            self.conn.execute(
+                "DELETE FROM interval_tags WHERE user_id=? AND start=?;",
+                (self.user, &start),
+            )?;
+            for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+                self.conn.execute(
+                    "INSERT OR IGNORE INTO tags (user_id,name) VALUES (?,?);",
+                    (self.user, tag),
+                )?;
+                self.conn.execute(
+                    "INSERT OR IGNORE INTO interval_tags (user_id,start,tag) SELECT ?1,?2,id FROM tags WHERE user_id=?1 AND name=?3;",
+                    (self.user, &start, tag),
+                )?;
+            }
+            Ok(())
+        })
+    }
+    /// Names of all tags ever used
+    pub fn get_tags(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM tags WHERE user_id=? ORDER BY name;")?;
+        let res = stmt.query_map((self.user,), |row| row.get(0))?;
+        Ok(res.collect::<rusqlite::Result<_>>()?)
+    }
+    /// Notes and tags of the intervals of a day, by start. Intervals without either are left out.
+    pub fn get_annotations_on_date(
+        &self,
+        date: &NaiveDate,
+    ) -> Result<HashMap<DateTime<Tz>, Annotation>> {
+        let zone = self.timezone()?;
+        let mut annotations: HashMap<DateTime<Tz>, Annotation> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT start,note FROM work_times WHERE user_id=? AND date=? AND note IS NOT NULL;",
+        )?;
+        for row in stmt.query_map((self.user, date), |row| {
+            Ok((row.get::<_, DateTime<Utc>>(0)?, row.get(1)?))
+        })? {
+            let (start, note) = row?;
+            annotations
+                .entry(start.with_timezone(&zone))
+                .or_default()
+                .note = Some(note);
+        }
+        let mut stmt = self.conn.prepare("SELECT interval_tags.start,tags.name FROM interval_tags JOIN tags ON tags.id=interval_tags.tag JOIN work_times ON work_times.user_id=interval_tags.user_id AND work_times.start=interval_tags.start WHERE interval_tags.user_id=? AND work_times.date=? ORDER BY tags.name;")?;
+        for row in stmt.query_map((self.user, date), |row| {
+            Ok((row.get::<_, DateTime<Utc>>(0)?, row.get(1)?))
+        })? {
+            let (start, tag) = row?;
+            annotations
+                .entry(start.with_timezone(&zone))
+                .or_default()
+                .tags
+                .push(tag);
+        }
+        Ok(annotations)
     }
 }
 
 impl<TP: TimeProvider> Drop for Database<'_, TP> {
     fn drop(&mut self) {
+        // Only the shutdown of the default user ends a workday when opening again
+        self.set_user(DEFAULT_USER);
         self.shutdown().ok();
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use super::{Database, TimeProvider};
-    use chrono::{Duration, Local, TimeZone};
-    use std::collections::HashSet;
+    use super::{
+        holiday_region_name, Absence, AbsenceKind, Annotation, Database, Error, TimeProvider,
+        WorkItem, WorkSchedule, DEFAULT_USER, HOLIDAY_REGIONS, MIGRATIONS, SCHEMA_VERSION,
+    };
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+    use chrono_tz::Tz;
+    use std::collections::{HashMap, HashSet};
 
     pub struct MockTime {
         time: std::cell::RefCell<chrono::DateTime<chrono::Utc>>,
+        zone: std::cell::Cell<Tz>,
     }
 
     impl MockTime {
+        /// Monday, 1990-01-01 9:00 UTC
         pub fn new() -> Self {
             let time = chrono::Utc.with_ymd_and_hms(1990, 1, 1, 9, 0, 0).unwrap();
             MockTime {
                 time: std::cell::RefCell::new(time),
+                zone: std::cell::Cell::new(Tz::UTC),
             }
         }
         pub fn advance(&self, hours: i64) {
             self.time
                 .replace_with(|t| *t + chrono::Duration::hours(hours));
         }
+        pub fn set(&self, time: chrono::DateTime<chrono::Utc>) {
+            self.time.replace(time);
+        }
+        pub fn set_timezone(&self, zone: Tz) {
+            self.zone.set(zone);
+        }
     }
 
     impl TimeProvider for MockTime {
         fn now(&self) -> chrono::DateTime<chrono::Utc> {
             self.time.borrow().clone()
         }
+        fn timezone(&self) -> Tz {
+            self.zone.get()
+        }
     }
 
     #[test]
@@ -181,6 +1447,279 @@ pub mod tests {
         assert_eq!(t1 + Duration::hours(1), t2);
     }
 
+    fn temp_db_path(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("timetrax_{}_{}.db", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+        path
+    }
+
+    #[test]
+    fn migrate_new_database() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(db.get_kv::<i64>("default_time").unwrap(), 7 * 60 * 60);
+    }
+
+    #[test]
+    fn migrate_unversioned_database() {
+        let path = temp_db_path("unversioned");
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE work_items (id INTEGER PRIMARY KEY ASC, name TEXT NOT NULL UNIQUE, description TEXT, visible BOOLEAN NOT NULL);
+                CREATE TABLE key_value (key TEXT PRIMARY KEY, value ANY);
+                INSERT INTO work_items(name, description, visible) VALUES ('old', NULL, 1);
+                INSERT INTO key_value(key, value) VALUES ('default_time', 3600);",
+            )
+            .unwrap();
+        }
+        {
+            let db = Database::open(&path, &chrono::Utc).unwrap();
+            assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+            assert_eq!(db.get_available_work().unwrap()[0].0, "old");
+            assert_eq!(db.get_kv::<i64>("default_time").unwrap(), 3600);
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn refuse_newer_database() {
+        let path = temp_db_path("newer");
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+                .unwrap();
+        }
+        let res = Database::open(&path, &chrono::Utc);
+        assert_eq!(
+            res.err(),
+            Some(Error::SchemaTooNew {
+                found: SCHEMA_VERSION + 1,
+                supported: SCHEMA_VERSION
+            })
+        );
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let tables: i64 = conn
+            .query_row("SELECT count(*) FROM sqlite_master", (), |row| row.get(0))
+            .unwrap();
+        assert_eq!(tables, 0, "Newer database must not be modified");
+        drop(conn);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn migrate_to_users() {
+        let path = temp_db_path("users");
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            for step in &MIGRATIONS[..5] {
+                conn.execute_batch(step).unwrap();
+            }
+            conn.execute_batch(
+                "PRAGMA user_version=5;
+                INSERT INTO work_items(name, description, visible) VALUES ('old', NULL, 1);
+                INSERT INTO work_times(start, work_item) VALUES ('2020-01-06T09:00:00Z', 1), ('2020-01-06T17:00:00Z', NULL);
+                INSERT INTO daily_summary(date, worked, expected) VALUES ('2020-01-06', 28800, 25200);",
+            )
+            .unwrap();
+        }
+        {
+            let db = Database::open(&path, &chrono::Utc).unwrap();
+            let date = chrono::NaiveDate::from_ymd_opt(2020, 1, 6).unwrap();
+            assert_eq!(db.user(), DEFAULT_USER);
+            assert_eq!(db.get_users().unwrap()[0].name, "admin");
+            assert_eq!(
+                db.get_available_work().unwrap(),
+                vec![("old".to_owned(), 1)]
+            );
+            assert_eq!(db.get_work_on_date(&date).unwrap().len(), 2);
+            assert_eq!(
+                db.get_summary_balance(date.succ_opt().unwrap()).unwrap(),
+                Duration::hours(1)
+            );
+            db.set_expected_time(date, 0).unwrap();
+            assert_eq!(
+                db.get_summarized_dates(date, date.succ_opt().unwrap())
+                    .unwrap(),
+                vec![],
+                "Triggers are recreated"
+            );
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn users_are_isolated() {
+        let t = MockTime::new();
+        let mut db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        db.set_current_work(Some(a)).unwrap();
+        db.set_kv("default_time", 3600).unwrap();
+
+        let other = db.create_user("other", Some("hash"), false).unwrap();
+        assert!(
+            db.create_user("other", None, false).is_err(),
+            "Unique names"
+        );
+        db.set_user(other);
+        assert_eq!(db.get_available_work().unwrap(), vec![]);
+        assert_eq!(db.get_current_work().unwrap(), None);
+        assert_eq!(db.get_kv::<i64>("default_time").unwrap(), 7 * 60 * 60);
+        assert_eq!(db.set_current_work(Some(a)), Err(Error::NoSuchItem(a)));
+        assert_eq!(
+            db.set_work_item_visible(a, false),
+            Err(Error::NoSuchItem(a))
+        );
+        db.add_work_item("a").unwrap();
+        db.set_current_work(Some(db.get_available_work().unwrap()[0].1))
+            .unwrap();
+
+        let (user, hash) = db.get_user_by_name("other").unwrap();
+        assert_eq!(
+            (user.id, user.admin, hash.as_deref()),
+            (other, false, Some("hash"))
+        );
+        db.create_session("token", other).unwrap();
+        assert_eq!(
+            db.get_session_user("token", t.now()).unwrap(),
+            Some(user.clone())
+        );
+        t.advance(1);
+        assert_eq!(
+            db.get_session_user("token", t.now()).unwrap(),
+            None,
+            "Expired"
+        );
+
+        db.set_user(DEFAULT_USER);
+        assert_eq!(db.get_current_work().unwrap(), Some(a));
+        db.delete_user(other).unwrap();
+        assert_eq!(db.get_users().unwrap().len(), 1);
+        assert_eq!(
+            db.get_user_by_name("other"),
+            Err(Error::NoSuchUser("other".to_owned()))
+        );
+    }
+
+    #[test]
+    fn holiday_region() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        let name = |db: &Database<_>| {
+            db.get_holiday_region()
+                .unwrap()
+                .map(|r| holiday_region_name(&r))
+        };
+        assert_eq!(name(&db), Some("BadenWuerttemberg"), "Default region");
+        for region in HOLIDAY_REGIONS.iter() {
+            db.set_holiday_region(Some(region)).unwrap();
+            assert_eq!(name(&db), Some(holiday_region_name(region)));
+        }
+        db.set_holiday_region(None).unwrap();
+        assert_eq!(name(&db), None, "No region");
+        db.set_kv("holiday_region", "Atlantis").unwrap();
+        assert!(db.get_holiday_region().is_err(), "Unknown region");
+    }
+
+    #[test]
+    fn holiday_region_recomputes_expected_time() {
+        let t = MockTime::new();
+        t.set(chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 1, 1, 9, 0, 0).unwrap());
+        let db = Database::open(":memory:", &t).unwrap();
+        let today = db.today().unwrap();
+        let earlier = today - chrono::Duration::days(3);
+        let tomorrow = today + chrono::Duration::days(1);
+        for date in [earlier, today, tomorrow] {
+            db.set_expected_time(date, 60 * 60).unwrap();
+        }
+        db.set_holiday_region(None).unwrap();
+        assert_eq!(
+            db.get_expected_work(earlier).unwrap(),
+            Some(chrono::Duration::hours(1)),
+            "Past days are kept"
+        );
+        assert_eq!(
+            db.get_expected_work(today).unwrap(),
+            Some(chrono::Duration::hours(7))
+        );
+        assert_eq!(
+            db.get_expected_work(tomorrow).unwrap(),
+            Some(chrono::Duration::hours(7))
+        );
+        db.set_holiday_region(Some(&HOLIDAY_REGIONS[0])).unwrap();
+        assert_eq!(
+            db.get_expected_work(today).unwrap(),
+            Some(chrono::Duration::zero()),
+            "New year"
+        );
+    }
+
+    #[test]
+    fn work_schedule() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2020, 1, d).unwrap();
+        assert_eq!(db.get_work_schedule(date(10)).unwrap(), None);
+        let full_time = WorkSchedule {
+            valid_from: date(6),
+            seconds: [8 * 3600, 8 * 3600, 8 * 3600, 8 * 3600, 8 * 3600, 0, 0],
+        };
+        let part_time = WorkSchedule {
+            valid_from: date(20),
+            seconds: [6 * 3600, 6 * 3600, 6 * 3600, 6 * 3600, 0, 0, 0],
+        };
+        db.set_work_schedule(&part_time).unwrap();
+        db.set_work_schedule(&full_time).unwrap();
+        assert_eq!(db.get_work_schedule(date(5)).unwrap(), None);
+        assert_eq!(
+            db.get_work_schedule(date(6)).unwrap().as_ref(),
+            Some(&full_time)
+        );
+        assert_eq!(
+            db.get_work_schedule(date(19)).unwrap().as_ref(),
+            Some(&full_time)
+        );
+        assert_eq!(
+            db.get_work_schedule(date(20)).unwrap().as_ref(),
+            Some(&part_time)
+        );
+        assert_eq!(
+            db.get_work_schedules().unwrap(),
+            vec![full_time.clone(), part_time.clone()]
+        );
+        assert_eq!(part_time.seconds_on(chrono::Weekday::Fri), 0);
+        db.remove_work_schedule(date(20)).unwrap();
+        assert_eq!(db.get_work_schedule(date(25)).unwrap(), Some(full_time));
+    }
+
+    #[test]
+    fn absences() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2020, 1, d).unwrap();
+        let vacation = Absence {
+            kind: AbsenceKind::Vacation,
+            half_day: false,
+        };
+        let sick = Absence {
+            kind: AbsenceKind::Sick,
+            half_day: true,
+        };
+        db.set_absence(date(2), Some(&vacation)).unwrap();
+        db.set_absence(date(3), Some(&vacation)).unwrap();
+        db.set_absence(date(3), Some(&sick)).unwrap();
+        db.set_absence(date(4), Some(&vacation)).unwrap();
+        db.set_absence(date(4), None).unwrap();
+        assert_eq!(db.get_absence(date(1)).unwrap(), None);
+        assert_eq!(db.get_absence(date(2)).unwrap(), Some(vacation));
+        assert_eq!(
+            db.get_absences(date(1), date(4)).unwrap(),
+            vec![(date(2), vacation), (date(3), sick)]
+        );
+        assert_eq!(db.get_vacation_entitlement(2020).unwrap(), None);
+        db.set_vacation_entitlement(2020, 30.0).unwrap();
+        assert_eq!(db.get_vacation_entitlement(2020).unwrap(), Some(30.0));
+    }
+
     #[test]
     fn add_get_work_item() {
         let db = Database::open(":memory:", &chrono::Utc).unwrap();
@@ -195,6 +1734,111 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn hide_rename_work_item() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        db.add_work_item("a").unwrap();
+        db.add_work_item("b").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        db.set_work_item_visible(a, false).unwrap();
+        let names: Vec<_> = db
+            .get_available_work()
+            .unwrap()
+            .into_iter()
+            .map(|x| x.0)
+            .collect();
+        assert_eq!(names, vec!["b"]);
+        db.rename_work_item(a, "c").unwrap();
+        assert!(
+            db.rename_work_item(a, "b").is_err(),
+            "Names have to be unique"
+        );
+        db.set_work_item_visible(a, true).unwrap();
+        assert!(db
+            .get_available_work()
+            .unwrap()
+            .contains(&("c".to_owned(), a)));
+        assert_eq!(
+            db.set_work_item_visible(1234, false),
+            Err(Error::NoSuchItem(1234))
+        );
+    }
+
+    #[test]
+    fn work_item_tree() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        db.add_work_item("web").unwrap();
+        db.add_work_item("app").unwrap();
+        let items: HashMap<_, _> = db.get_available_work().unwrap().into_iter().collect();
+        let (web, app) = (items["web"], items["app"]);
+        db.add_sub_item(Some(web), "design").unwrap();
+        assert_eq!(
+            db.add_sub_item(Some(app), "design").unwrap(),
+            1,
+            "Names are unique per parent"
+        );
+        assert_eq!(db.add_sub_item(Some(app), "design").unwrap(), 0);
+        assert_eq!(db.add_work_item("web").unwrap(), 0);
+        let items: HashMap<_, _> = db.get_available_work().unwrap().into_iter().collect();
+        let design = items["web / design"];
+        assert!(items.contains_key("app / design"));
+
+        db.add_sub_item(Some(design), "logo").unwrap();
+        let logo = db.get_available_work().unwrap().last().unwrap().1;
+        assert_eq!(
+            db.get_work_item_names().unwrap()[&logo],
+            "web / design / logo"
+        );
+        assert_eq!(
+            db.set_work_item_parent(web, Some(logo)),
+            Err(Error::InvalidParent(web))
+        );
+        db.set_work_item_parent(design, Some(app)).unwrap_err();
+        db.set_work_item_parent(design, None).unwrap();
+        assert_eq!(db.get_work_item_names().unwrap()[&logo], "design / logo");
+        assert_eq!(
+            db.get_work_items().unwrap()[0],
+            WorkItem {
+                id: app,
+                parent: None,
+                name: "app".to_owned(),
+                description: None,
+                visible: true,
+                archived: false,
+            }
+        );
+    }
+
+    #[test]
+    fn archive_describe_work_item() {
+        let db = Database::open(":memory:", &chrono::Utc).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        db.set_work_item_description(a, Some("Project A")).unwrap();
+        db.set_work_item_archived(a, true).unwrap();
+        assert!(db.get_available_work().unwrap().is_empty());
+        assert_eq!(
+            db.get_work_items().unwrap(),
+            vec![WorkItem {
+                id: a,
+                parent: None,
+                name: "a".to_owned(),
+                description: Some("Project A".to_owned()),
+                visible: true,
+                archived: true,
+            }]
+        );
+        assert_eq!(db.get_work_item_names().unwrap()[&a], "a");
+        db.set_work_item_archived(a, false).unwrap();
+        db.set_work_item_description(a, None).unwrap();
+        assert_eq!(db.get_available_work().unwrap().len(), 1);
+        assert_eq!(db.get_work_items().unwrap()[0].description, None);
+        assert_eq!(
+            db.set_work_item_archived(1234, true),
+            Err(Error::NoSuchItem(1234))
+        );
+    }
+
     #[test]
     fn get_set_current_work() {
         let t = MockTime::new();
@@ -207,6 +1851,155 @@ pub mod tests {
         assert_eq!(db.get_current_work().unwrap(), Some(work_item));
     }
 
+    #[test]
+    fn edit_intervals() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        db.add_work_item("b").unwrap();
+        let work = db.get_available_work().unwrap();
+        let (a, b) = (Some(work[0].1), Some(work[1].1));
+        let date = t.now().date_naive();
+        let at = |h, m| chrono::Utc.with_ymd_and_hms(1990, 1, 1, h, m, 0).unwrap();
+        let day = |db: &Database<_>| -> Vec<_> {
+            db.get_work_on_date(&date)
+                .unwrap()
+                .into_iter()
+                .map(|(w, t)| (w, t.with_timezone(&chrono::Utc)))
+                .collect()
+        };
+        db.set_current_work(a).unwrap();
+        t.advance(1);
+        db.set_current_work(None).unwrap();
+        t.advance(24);
+
+        db.insert_work_switch(at(9, 30), b).unwrap();
+        assert_eq!(
+            day(&db),
+            vec![(a, at(9, 0)), (b, at(9, 30)), (None, at(10, 0))]
+        );
+        assert_eq!(
+            db.insert_work_switch(at(9, 30), a),
+            Err(Error::DuplicateEntry(at(9, 30)))
+        );
+        assert_eq!(
+            db.insert_work_switch(at(11, 0), a),
+            Err(Error::MalformedDay(date))
+        );
+        assert_eq!(day(&db).len(), 3, "Failed edit is rolled back");
+
+        db.move_work_switch(at(9, 30), at(9, 45)).unwrap();
+        assert_eq!(
+            day(&db),
+            vec![(a, at(9, 0)), (b, at(9, 45)), (None, at(10, 0))]
+        );
+        assert_eq!(
+            db.move_work_switch(at(9, 45), at(10, 15)),
+            Err(Error::InvalidMove(at(9, 45)))
+        );
+        assert_eq!(
+            db.move_work_switch(at(9, 50), at(9, 55)),
+            Err(Error::NoSuchEntry(at(9, 50)))
+        );
+        db.move_work_switch(at(10, 0), at(10, 15)).unwrap();
+
+        db.set_interval_work(at(9, 45), a).unwrap();
+        assert_eq!(day(&db), vec![(a, at(9, 0)), (None, at(10, 15))]);
+        db.insert_work_switch(at(9, 30), b).unwrap();
+        db.insert_work_switch(at(9, 45), a).unwrap();
+        db.delete_interval(at(9, 30)).unwrap();
+        assert_eq!(
+            day(&db),
+            vec![
+                (a, at(9, 0)),
+                (None, at(9, 30)),
+                (a, at(9, 45)),
+                (None, at(10, 15))
+            ]
+        );
+        db.delete_interval(at(9, 0)).unwrap();
+        assert_eq!(day(&db), vec![(a, at(9, 45)), (None, at(10, 15))]);
+        db.delete_interval(at(9, 45)).unwrap();
+        assert_eq!(day(&db), vec![]);
+    }
+
+    #[test]
+    fn annotate_intervals() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        let start = t.now();
+        let date = start.date_naive();
+        assert_eq!(db.get_current_interval(), Ok(None));
+        db.set_current_work(Some(a)).unwrap();
+        assert_eq!(db.get_current_interval(), Ok(Some(start)));
+        db.set_interval_note(start, Some("planning")).unwrap();
+        db.set_interval_tags(start, &["meeting".to_owned(), " billable ".to_owned()])
+            .unwrap();
+        let annotation = Annotation {
+            note: Some("planning".to_owned()),
+            tags: vec!["billable".to_owned(), "meeting".to_owned()],
+        };
+        let local = start.with_timezone(&Tz::UTC);
+        assert_eq!(
+            db.get_annotations_on_date(&date).unwrap(),
+            HashMap::from([(local, annotation.clone())])
+        );
+        assert_eq!(
+            db.set_interval_note(start + Duration::hours(1), None),
+            Err(Error::NoSuchEntry(start + Duration::hours(1)))
+        );
+
+        // Annotations follow a moved switch
+        t.advance(1);
+        db.set_current_work(None).unwrap();
+        assert_eq!(db.get_current_interval(), Ok(None));
+        let moved = start - Duration::minutes(30);
+        db.move_work_switch(start, moved).unwrap();
+        assert_eq!(
+            db.get_annotations_on_date(&date).unwrap(),
+            HashMap::from([(moved.with_timezone(&Tz::UTC), annotation)])
+        );
+
+        db.set_interval_tags(moved, &["review".to_owned()]).unwrap();
+        db.set_interval_note(moved, None).unwrap();
+        assert_eq!(
+            db.get_annotations_on_date(&date).unwrap()[&moved.with_timezone(&Tz::UTC)],
+            Annotation {
+                note: None,
+                tags: vec!["review".to_owned()],
+            }
+        );
+        assert_eq!(
+            db.get_tags().unwrap(),
+            vec!["billable", "meeting", "review"]
+        );
+
+        // Merged intervals keep the annotations of both
+        db.add_work_item("b").unwrap();
+        let b = db.get_available_work().unwrap()[1].1;
+        let later = moved + Duration::minutes(10);
+        db.insert_work_switch(later, Some(b)).unwrap();
+        db.set_interval_note(moved, Some("agenda")).unwrap();
+        db.set_interval_note(later, Some("notes")).unwrap();
+        db.set_interval_tags(later, &["meeting".to_owned(), "review".to_owned()])
+            .unwrap();
+        db.set_interval_work(later, Some(a)).unwrap();
+        assert_eq!(
+            db.get_annotations_on_date(&date).unwrap(),
+            HashMap::from([(
+                moved.with_timezone(&Tz::UTC),
+                Annotation {
+                    note: Some("agenda; notes".to_owned()),
+                    tags: vec!["meeting".to_owned(), "review".to_owned()],
+                }
+            )])
+        );
+        db.delete_interval(moved).unwrap();
+        assert!(db.get_annotations_on_date(&date).unwrap().is_empty());
+    }
+
     #[test]
     fn shutdown() {
         let t = MockTime::new();
@@ -214,9 +2007,9 @@ pub mod tests {
         db.add_work_item("test").unwrap();
         let work_item = Some(db.get_available_work().unwrap().first().unwrap().1);
         db.set_current_work(work_item).unwrap();
-        let start_time = t.now().with_timezone(&Local);
+        let start_time = t.now().with_timezone(&Tz::UTC);
         t.advance(1);
-        let end_time = t.now().with_timezone(&Local);
+        let end_time = t.now().with_timezone(&Tz::UTC);
         db.shutdown().unwrap();
         db.add_work_end_at_shutdown().unwrap(); // Same day. Should do nothing
         let today = db.get_work_on_date(&t.now().date_naive()).unwrap();
@@ -234,4 +2027,97 @@ pub mod tests {
         let today = db.get_work_on_date(&t.now().date_naive()).unwrap();
         assert_eq!(today, vec![(work_item, start_time), (None, end_time)]);
     }
+
+    #[test]
+    fn drop_records_shutdown_of_default_user() {
+        let path = temp_db_path("drop_shutdown");
+        let other = {
+            let mut db = Database::open(&path, &chrono::Utc).unwrap();
+            let other = db.create_user("other", None, false).unwrap();
+            db.set_user(other);
+            other
+        };
+        let mut db = Database::open(&path, &chrono::Utc).unwrap();
+        assert!(db
+            .get_kv_optional::<chrono::DateTime<chrono::Utc>>("shutdown")
+            .unwrap()
+            .is_some());
+        db.set_user(other);
+        assert_eq!(
+            db.get_kv_optional::<chrono::DateTime<chrono::Utc>>("shutdown")
+                .unwrap(),
+            None
+        );
+        drop(db);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn days_in_time_zones() {
+        let t = MockTime::new();
+        let berlin = chrono_tz::Europe::Berlin;
+        t.set_timezone(berlin);
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        let local = |day, hour, minute| {
+            berlin
+                .with_ymd_and_hms(2023, 3, day, hour, minute, 0)
+                .unwrap()
+        };
+        let day = |day| NaiveDate::from_ymd_opt(2023, 3, day).unwrap();
+        // Saturday until 23:00 and Sunday from 0:30, which is still Saturday in UTC. On Sunday
+        // the clocks are changed from 2:00 to 3:00.
+        for (time, work_item) in [
+            (local(25, 22, 0), Some(a)),
+            (local(25, 23, 0), None),
+            (local(26, 0, 30), Some(a)),
+            (local(26, 4, 0), None),
+        ] {
+            t.set(time.with_timezone(&Utc));
+            db.set_current_work(work_item).unwrap();
+        }
+        assert_eq!(
+            db.get_work_on_date(&day(25)).unwrap(),
+            vec![(Some(a), local(25, 22, 0)), (None, local(25, 23, 0))]
+        );
+        let sunday = db.get_work_on_date(&day(26)).unwrap();
+        assert_eq!(
+            sunday,
+            vec![(Some(a), local(26, 0, 30)), (None, local(26, 4, 0))]
+        );
+        assert_eq!(sunday[1].1 - sunday[0].1, Duration::minutes(150));
+        assert_eq!(
+            db.day_start(day(27)).unwrap() - db.day_start(day(26)).unwrap(),
+            Duration::hours(23)
+        );
+        let autumn = |day| NaiveDate::from_ymd_opt(2023, 10, day).unwrap();
+        assert_eq!(
+            db.day_start(autumn(30)).unwrap() - db.day_start(autumn(29)).unwrap(),
+            Duration::hours(25)
+        );
+
+        // After travelling to New York, where it's still Sunday, new entries belong to the days
+        // there. Recorded ones keep their day and zone.
+        t.set(local(27, 3, 0).with_timezone(&Utc));
+        db.set_timezone(Some(chrono_tz::America::New_York)).unwrap();
+        assert_eq!(db.today().unwrap(), day(26));
+        db.set_current_work(Some(a)).unwrap();
+        let sunday: Vec<_> = db
+            .get_work_on_date(&day(26))
+            .unwrap()
+            .into_iter()
+            .map(|(_, start)| start.to_string())
+            .collect();
+        assert_eq!(
+            sunday,
+            [
+                "2023-03-26 00:30:00 CET",
+                "2023-03-26 04:00:00 CEST",
+                "2023-03-26 21:00:00 EDT"
+            ]
+        );
+        assert_eq!(db.get_work_on_date(&day(25)).unwrap().len(), 2);
+        assert_eq!(db.get_current_work(), Ok(Some(a)));
+    }
 }
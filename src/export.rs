@@ -0,0 +1,206 @@
+//! Export of work times as CSV, for spreadsheets and payroll. Ranges include both ends.
+use crate::business_logic::{self, get_work_time_in_range, work_done_until_now};
+use crate::database::{Database, TimeProvider};
+use chrono::{Duration, NaiveDate, SecondsFormat};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Csv(csv::Error),
+    Logic(business_logic::Error),
+}
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        Self::Csv(value)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Csv(value.into())
+    }
+}
+impl From<business_logic::Error> for Error {
+    fn from(value: business_logic::Error) -> Self {
+        Self::Logic(value)
+    }
+}
+impl From<crate::database::Error> for Error {
+    fn from(value: crate::database::Error) -> Self {
+        Self::Logic(value.into())
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv(e) => e.fmt(f),
+            Self::Logic(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date + Duration::days(1)
+}
+
+/// One row per interval with work, with its note and comma separated tags. Intervals without
+/// end (still running or missing end of workday) have empty end and duration.
+pub fn write_intervals<T: TimeProvider, W: std::io::Write>(
+    db: &Database<T>,
+    from: NaiveDate,
+    to: NaiveDate,
+    writer: W,
+) -> Result<(), Error> {
+    let names = db.get_work_item_names()?;
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record([
+        "date",
+        "start",
+        "end",
+        "duration_seconds",
+        "work_item",
+        "note",
+        "tags",
+    ])?;
+    let mut date = from;
+    while date <= to {
+        let times = db.get_work_on_date(&date)?;
+        let mut annotations = db.get_annotations_on_date(&date)?;
+        for (i, (work_item, start)) in times.iter().enumerate() {
+            let Some(work_item) = work_item else {
+                continue;
+            };
+            let end = times.get(i + 1).map(|next| next.1);
+            let annotation = annotations.remove(start).unwrap_or_default();
+            csv.write_record([
+                date.to_string(),
+                start.to_rfc3339_opts(SecondsFormat::Secs, false),
+                end.map(|end| end.to_rfc3339_opts(SecondsFormat::Secs, false))
+                    .unwrap_or_default(),
+                end.map(|end| (end - *start).num_seconds().to_string())
+                    .unwrap_or_default(),
+                names
+                    .get(work_item)
+                    .cloned()
+                    .unwrap_or_else(|| format!("#{}", work_item)),
+                annotation.note.unwrap_or_default(),
+                annotation.tags.join(","),
+            ])?;
+        }
+        date = next_day(date);
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// One row per day with worked and expected time. Worked time and difference are empty for
+/// days without end of workday. Today counts until now.
+pub fn write_days<T: TimeProvider, W: std::io::Write>(
+    db: &Database<T>,
+    from: NaiveDate,
+    to: NaiveDate,
+    writer: W,
+) -> Result<(), Error> {
+    let today = db.today()?;
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record([
+        "date",
+        "worked_seconds",
+        "expected_seconds",
+        "difference_seconds",
+        "absence",
+    ])?;
+    for (date, mut day) in get_work_time_in_range(db, from, next_day(to))? {
+        if date == today {
+            day.work_done = work_done_until_now(db, date);
+        }
+        let worked = match day.work_done {
+            Ok(worked) => Some(worked),
+            Err(business_logic::Error::Inconsistent(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+        csv.write_record([
+            date.to_string(),
+            worked
+                .map(|w| w.num_seconds().to_string())
+                .unwrap_or_default(),
+            day.expected.num_seconds().to_string(),
+            worked
+                .map(|w| (w - day.expected).num_seconds().to_string())
+                .unwrap_or_default(),
+            day.absence
+                .map(|a| a.kind.name().to_owned())
+                .unwrap_or_default(),
+        ])?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// Write both exports into `dir`, returning the paths of the interval and day files
+pub fn export_to_dir<T: TimeProvider>(
+    db: &Database<T>,
+    from: NaiveDate,
+    to: NaiveDate,
+    dir: &Path,
+) -> Result<(PathBuf, PathBuf), Error> {
+    std::fs::create_dir_all(dir)?;
+    let intervals = dir.join(format!("timetrax-intervals-{}-{}.csv", from, to));
+    let days = dir.join(format!("timetrax-days-{}-{}.csv", from, to));
+    write_intervals(db, from, to, std::fs::File::create(&intervals)?)?;
+    write_days(db, from, to, std::fs::File::create(&days)?)?;
+    Ok((intervals, days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_days, write_intervals};
+    use crate::database::{tests::MockTime, Database, TimeProvider};
+
+    #[test]
+    fn export_csv() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.add_work_item("a, b").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        let date = t.now().date_naive();
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(2);
+        db.set_current_work(None).unwrap();
+        t.advance(1);
+        db.set_current_work(Some(a)).unwrap();
+        db.set_interval_note(t.now(), Some("review")).unwrap();
+        db.set_interval_tags(t.now(), &["billable".to_owned(), "meeting".to_owned()])
+            .unwrap();
+        t.advance(1);
+        db.set_work_item_visible(a, false).unwrap();
+
+        let mut out = Vec::new();
+        write_intervals(&db, date, date, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,start,end,duration_seconds,work_item,note,tags\n\
+             1990-01-01,1990-01-01T09:00:00+00:00,1990-01-01T11:00:00+00:00,7200,\"a, b\",,\n\
+             1990-01-01,1990-01-01T12:00:00+00:00,,,\"a, b\",review,\"billable,meeting\"\n"
+        );
+
+        let mut out = Vec::new();
+        write_days(&db, date, date, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,worked_seconds,expected_seconds,difference_seconds,absence\n\
+             1990-01-01,10800,25200,-14400,\n"
+        );
+        t.advance(24);
+        let mut out = Vec::new();
+        write_days(&db, date, date + chrono::Duration::days(1), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "date,worked_seconds,expected_seconds,difference_seconds,absence\n\
+             1990-01-01,,25200,,\n\
+             1990-01-02,0,25200,-25200,\n"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Detection of time away from the computer while work is running. When the user returns the
+//! idle period can be kept, discarded or assigned to another work item.
+use crate::database::{Database, Result, TimeProvider};
+use chrono::{DateTime, Duration, Utc};
+
+/// Tells for how long the user has not used the computer
+pub trait IdleSource {
+    /// None if it can't be determined
+    fn idle_time(&self) -> Option<Duration>;
+}
+
+/// Idle time of the X screensaver (via `xprintidle`), falling back to the idle hint of the
+/// logind session
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+pub struct LinuxIdle;
+
+#[cfg(target_os = "linux")]
+impl LinuxIdle {
+    fn output(program: &str, args: &[&str]) -> Option<String> {
+        let output = std::process::Command::new(program)
+            .args(args)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        String::from_utf8(output.stdout).ok()
+    }
+
+    fn screensaver() -> Option<Duration> {
+        let millis = Self::output("xprintidle", &[])?.trim().parse().ok()?;
+        Some(Duration::milliseconds(millis))
+    }
+
+    fn logind() -> Option<Duration> {
+        let session = std::env::var("XDG_SESSION_ID").ok()?;
+        let output = Self::output(
+            "loginctl",
+            &[
+                "show-session",
+                &session,
+                "--property=IdleHint",
+                "--property=IdleSinceHint",
+            ],
+        )?;
+        let mut idle = false;
+        let mut since = None;
+        for line in output.lines() {
+            match line.split_once('=') {
+                Some(("IdleHint", value)) => idle = value == "yes",
+                Some(("IdleSinceHint", value)) => since = value.parse::<i64>().ok(),
+                _ => {}
+            }
+        }
+        if !idle {
+            return Some(Duration::zero());
+        }
+        // Microseconds since the epoch
+        let since = DateTime::<Utc>::from_timestamp(since? / 1_000_000, 0)?;
+        Some((Utc::now() - since).max(Duration::zero()))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl IdleSource for LinuxIdle {
+    fn idle_time(&self) -> Option<Duration> {
+        Self::screensaver().or_else(Self::logind)
+    }
+}
+
+/// Idle time measured before by another source, e.g. off the UI thread as measuring may block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeasuredIdle(pub Option<Duration>);
+
+impl IdleSource for MeasuredIdle {
+    fn idle_time(&self) -> Option<Duration> {
+        self.0
+    }
+}
+
+/// Time without activity while `work_item` was running
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdlePeriod {
+    pub start: DateTime<Utc>,
+    /// When the user returned
+    pub end: DateTime<Utc>,
+    pub work_item: u64,
+}
+
+/// What to do with an idle period
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    /// Count it as work on the item that was running
+    Keep,
+    /// Turn it into a break
+    Discard,
+    /// Count it as work on another item
+    Reassign(u64),
+}
+
+/// Watches an `IdleSource` and reports idle periods during work once the user is back
+pub struct IdleTracker {
+    /// Shorter idle periods are ignored
+    threshold: Duration,
+    /// Start of the current idle period and the work running then
+    idle: Option<(DateTime<Utc>, u64)>,
+}
+
+impl IdleTracker {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            idle: None,
+        }
+    }
+
+    /// Check the idle time of `source`, to be called regularly. Returns the idle period when
+    /// the user returns after being idle for at least the threshold while working.
+    pub fn poll<S: IdleSource + ?Sized, T: TimeProvider>(
+        &mut self,
+        source: &S,
+        db: &Database<T>,
+    ) -> Result<Option<IdlePeriod>> {
+        let Some(idle_time) = source.idle_time() else {
+            return Ok(None);
+        };
+        let now = db.now();
+        if idle_time >= self.threshold {
+            if self.idle.is_none() {
+                if let Some(work_item) = db.get_current_work()? {
+                    self.idle = Some((now - idle_time, work_item));
+                }
+            }
+            return Ok(None);
+        }
+        Ok(self.idle.take().map(|(start, work_item)| IdlePeriod {
+            start,
+            end: now - idle_time,
+            work_item,
+        }))
+    }
+}
+
+/// Rewrite the work times of an idle period. A period crossing midnight is split at the day
+/// boundary, the days before the last one end with it.
+pub fn resolve<T: TimeProvider>(
+    db: &Database<T>,
+    period: &IdlePeriod,
+    resolution: Resolution,
+) -> Result<()> {
+    let work_item = match resolution {
+        Resolution::Keep => return Ok(()),
+        Resolution::Discard => None,
+        Resolution::Reassign(work_item) => Some(work_item),
+    };
+    db.with_savepoint(true, || {
+        let mut start = period.start;
+        loop {
+            let next_day = db
+                .day_start(db.local_date(&start)? + Duration::days(1))?
+                .with_timezone(&Utc);
+            if period.end < next_day {
+                return db.set_work_between(start, period.end, work_item);
+            }
+            db.set_work_until_day_end(start, work_item)?;
+            if period.end == next_day {
+                return Ok(());
+            }
+            start = next_day;
+        }
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::{resolve, IdlePeriod, IdleSource, IdleTracker, Resolution};
+    use crate::database::{tests::MockTime, Database, TimeProvider};
+    use chrono::{Duration, Utc};
+    use chrono_tz::Tz;
+    use std::cell::Cell;
+
+    /// Idle time set by the test
+    #[derive(Default)]
+    pub struct MockIdle(pub Cell<Option<Duration>>);
+
+    impl IdleSource for MockIdle {
+        fn idle_time(&self) -> Option<Duration> {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn track_and_resolve_idle_time() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        db.add_work_item("b").unwrap();
+        let work = db.get_available_work().unwrap();
+        let (a, b) = (work[0].1, work[1].1);
+        let mut tracker = IdleTracker::new(Duration::minutes(5));
+        let idle = MockIdle::default();
+        let mut poll = |idle_time: Option<Duration>| {
+            idle.0.set(idle_time);
+            tracker.poll(&idle, &db)
+        };
+        let start = t.now();
+        let hours = Duration::hours;
+
+        assert_eq!(poll(None), Ok(None), "Unknown idle time");
+        // Idle without work doesn't matter
+        assert_eq!(poll(Some(Duration::minutes(10))), Ok(None));
+        assert_eq!(poll(Some(Duration::zero())), Ok(None));
+
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(2);
+        assert_eq!(
+            poll(Some(Duration::minutes(2))),
+            Ok(None),
+            "Below the threshold"
+        );
+        assert_eq!(poll(Some(hours(1))), Ok(None));
+        t.advance(1);
+        assert_eq!(poll(Some(hours(2))), Ok(None));
+        let period = IdlePeriod {
+            start: start + hours(1),
+            end: start + hours(3),
+            work_item: a,
+        };
+        assert_eq!(poll(Some(Duration::zero())), Ok(Some(period.clone())));
+        assert_eq!(poll(Some(Duration::zero())), Ok(None), "Reported only once");
+
+        let day = |db: &Database<MockTime>| {
+            db.get_work_on_date(&start.date_naive())
+                .unwrap()
+                .into_iter()
+                .map(|(work_item, time)| (work_item, time - start.with_timezone(&Tz::UTC)))
+                .collect::<Vec<_>>()
+        };
+        resolve(&db, &period, Resolution::Keep).unwrap();
+        assert_eq!(day(&db), vec![(Some(a), hours(0))]);
+        resolve(&db, &period, Resolution::Reassign(b)).unwrap();
+        assert_eq!(
+            day(&db),
+            vec![
+                (Some(a), hours(0)),
+                (Some(b), hours(1)),
+                (Some(a), hours(3))
+            ]
+        );
+        resolve(&db, &period, Resolution::Discard).unwrap();
+        assert_eq!(
+            day(&db),
+            vec![(Some(a), hours(0)), (None, hours(1)), (Some(a), hours(3))]
+        );
+    }
+
+    #[test]
+    fn resolve_idle_time_over_midnight() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("a").unwrap();
+        db.add_work_item("b").unwrap();
+        let work = db.get_available_work().unwrap();
+        let (a, b) = (work[0].1, work[1].1);
+        let hours = Duration::hours;
+        let midnight = t.now().date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        t.advance(11);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(5);
+        let period = IdlePeriod {
+            start: midnight + hours(22),
+            end: midnight + hours(25),
+            work_item: a,
+        };
+        let day = |db: &Database<MockTime>, date| {
+            db.get_work_on_date(&date)
+                .unwrap()
+                .into_iter()
+                .map(|(work_item, time)| (work_item, time.with_timezone(&Utc) - midnight))
+                .collect::<Vec<_>>()
+        };
+        let (first, second) = (midnight.date_naive(), db.today().unwrap());
+
+        resolve(&db, &period, Resolution::Discard).unwrap();
+        assert_eq!(
+            day(&db, first),
+            vec![(Some(a), hours(20)), (None, hours(22))]
+        );
+        assert_eq!(day(&db, second), vec![]);
+
+        resolve(&db, &period, Resolution::Reassign(b)).unwrap();
+        let day_end = hours(24) - Duration::seconds(1);
+        assert_eq!(
+            day(&db, first),
+            vec![(Some(a), hours(20)), (Some(b), hours(22)), (None, day_end)]
+        );
+        assert_eq!(
+            day(&db, second),
+            vec![(Some(b), hours(24)), (None, hours(25))]
+        );
+    }
+}
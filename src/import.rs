@@ -0,0 +1,450 @@
+//! Import of the exports of other time trackers. Work items are created by name as needed.
+//! Entries overlapping recorded work or each other are reported as conflicts and skipped.
+//! Entries crossing midnight are split into one part per day, each imported on its own.
+use crate::database::{self, Database, TimeProvider};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum Error {
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Parse { record: usize, message: String },
+    Db(database::Error),
+}
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        Self::Csv(value)
+    }
+}
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+impl From<database::Error> for Error {
+    fn from(value: database::Error) -> Self {
+        Self::Db(value)
+    }
+}
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv(e) => e.fmt(f),
+            Self::Json(e) => e.fmt(f),
+            Self::Parse { record, message } => write!(f, "Entry {}: {}", record, message),
+            Self::Db(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Detailed report of Toggl Track as CSV
+    Toggl,
+    /// Detailed report of Clockify as CSV
+    Clockify,
+    /// Output of `timew export`
+    Timewarrior,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toggl" => Ok(Self::Toggl),
+            "clockify" => Ok(Self::Clockify),
+            "timewarrior" => Ok(Self::Timewarrior),
+            _ => Err(format!(
+                "Unknown format '{}'. Use toggl, clockify or timewarrior",
+                s
+            )),
+        }
+    }
+}
+
+/// Name of the item for entries without project or tags
+const NO_PROJECT: &str = "No project";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub work_item: String,
+    pub start: DateTime<Utc>,
+    /// None if the work is still running
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub entry: Entry,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// Number of entries written, counting every day of a split entry
+    pub imported: usize,
+    /// Work items that didn't exist before
+    pub new_items: Vec<String>,
+    /// Entries that were skipped
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Local times of CSV exports are read in `zone`
+pub fn parse<R: std::io::Read>(format: Format, reader: R, zone: Tz) -> Result<Vec<Entry>, Error> {
+    match format {
+        Format::Toggl | Format::Clockify => parse_csv(reader, zone),
+        Format::Timewarrior => parse_timewarrior(reader),
+    }
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(s, format).ok())
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(s, format).ok())
+}
+
+/// Toggl and Clockify both export local times with separate date and time columns, only the
+/// capitalization of the headers and the date format differ
+fn parse_csv<R: std::io::Read>(reader: R, zone: Tz) -> Result<Vec<Entry>, Error> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let headers: HashMap<String, usize> = csv
+        .headers()?
+        .iter()
+        .enumerate()
+        .map(|(i, header)| (header.trim().to_lowercase(), i))
+        .collect();
+    let column = |name: &str| {
+        headers.get(name).copied().ok_or_else(|| Error::Parse {
+            record: 0,
+            message: format!("Missing column '{}'", name),
+        })
+    };
+    let project = column("project")?;
+    let description = column("description")?;
+    let start_date = column("start date")?;
+    let start_time = column("start time")?;
+    let end_date = column("end date")?;
+    let end_time = column("end time")?;
+
+    let mut entries = Vec::new();
+    for (i, record) in csv.records().enumerate() {
+        let record = record?;
+        let field = |column: usize| record.get(column).unwrap_or_default().trim();
+        let local = |date: usize, time: usize| -> Result<DateTime<Utc>, Error> {
+            let invalid = || Error::Parse {
+                record: i + 1,
+                message: format!("Invalid time '{} {}'", field(date), field(time)),
+            };
+            let date = parse_date(field(date)).ok_or_else(invalid)?;
+            let time = parse_time(field(time)).ok_or_else(invalid)?;
+            Ok(zone
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .ok_or_else(invalid)?
+                .with_timezone(&Utc))
+        };
+        let name = [field(project), field(description)]
+            .into_iter()
+            .find(|name| !name.is_empty())
+            .unwrap_or(NO_PROJECT);
+        entries.push(Entry {
+            work_item: name.to_owned(),
+            start: local(start_date, start_time)?,
+            end: Some(local(end_date, end_time)?),
+        });
+    }
+    Ok(entries)
+}
+
+#[derive(Deserialize)]
+struct TimewarriorInterval {
+    start: String,
+    end: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn parse_timewarrior<R: std::io::Read>(reader: R) -> Result<Vec<Entry>, Error> {
+    let intervals: Vec<TimewarriorInterval> = serde_json::from_reader(reader)?;
+    let time = |record: usize, s: &str| {
+        NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ")
+            .map(|t| t.and_utc())
+            .map_err(|_| Error::Parse {
+                record,
+                message: format!("Invalid time '{}'", s),
+            })
+    };
+    intervals
+        .into_iter()
+        .enumerate()
+        .map(|(i, interval)| {
+            Ok(Entry {
+                work_item: if interval.tags.is_empty() {
+                    NO_PROJECT.to_owned()
+                } else {
+                    interval.tags.join(", ")
+                },
+                start: time(i + 1, &interval.start)?,
+                end: interval.end.map(|end| time(i + 1, &end)).transpose()?,
+            })
+        })
+        .collect()
+}
+
+/// Parts of `entry` on each local day it touches. All but the last end a second before
+/// midnight, as a day can't end on the next one.
+fn split_at_days<T: TimeProvider>(db: &Database<T>, mut entry: Entry) -> Result<Vec<Entry>, Error> {
+    let mut parts = Vec::new();
+    if let Some(end) = entry.end {
+        loop {
+            let next_day = db
+                .day_start(db.local_date(&entry.start)? + Duration::days(1))?
+                .with_timezone(&Utc);
+            if end < next_day {
+                break;
+            }
+            parts.push(Entry {
+                end: Some(next_day - Duration::seconds(1)),
+                ..entry.clone()
+            });
+            if end == next_day {
+                return Ok(parts);
+            }
+            entry.start = next_day;
+        }
+    }
+    parts.push(entry);
+    Ok(parts)
+}
+
+/// Write `entries` in order. With `dry_run` nothing is kept, but the report is the same.
+pub fn import<T: TimeProvider>(
+    db: &Database<T>,
+    entries: Vec<Entry>,
+    dry_run: bool,
+) -> Result<Report, Error> {
+    let item_ids = || -> Result<HashMap<String, u64>, Error> {
+        Ok(db
+            .get_work_item_names()?
+            .into_iter()
+            .map(|(id, name)| (name, id))
+            .collect())
+    };
+    db.with_savepoint(!dry_run, || {
+        let mut items = item_ids()?;
+        let mut report = Report::default();
+        for entry in entries {
+            let work_item = match items.get(&entry.work_item) {
+                Some(id) => *id,
+                None => {
+                    db.add_work_item(&entry.work_item)?;
+                    items = item_ids()?;
+                    report.new_items.push(entry.work_item.clone());
+                    items[&entry.work_item]
+                }
+            };
+            for part in split_at_days(db, entry)? {
+                match db.insert_interval(part.start, part.end, work_item) {
+                    Ok(()) => report.imported += 1,
+                    Err(database::Error::Sqlite(e)) => {
+                        return Err(database::Error::Sqlite(e).into())
+                    }
+                    Err(e) => report.conflicts.push(Conflict {
+                        entry: part,
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+        }
+        Ok(report)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, parse, Entry, Format};
+    use crate::database::{tests::MockTime, Database};
+    use chrono::{DateTime, NaiveDate, Utc};
+    use chrono_tz::Tz;
+
+    fn local(date: &str, time: &str) -> DateTime<Utc> {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .unwrap()
+            .and_time(time.parse().unwrap())
+            .and_local_timezone(Tz::UTC)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse_formats() {
+        let toggl = "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags\n\
+                     Me,me@example.com,,Website,,Layout,No,1989-12-01,08:00:00,1989-12-01,09:30:00,01:30:00,\n\
+                     Me,me@example.com,,,,,No,1989-12-01,10:00:00,1989-12-01,11:00:00,01:00:00,\n";
+        assert_eq!(
+            parse(Format::Toggl, toggl.as_bytes(), Tz::UTC).unwrap(),
+            vec![
+                Entry {
+                    work_item: "Website".to_owned(),
+                    start: local("1989-12-01", "08:00:00"),
+                    end: Some(local("1989-12-01", "09:30:00")),
+                },
+                Entry {
+                    work_item: "No project".to_owned(),
+                    start: local("1989-12-01", "10:00:00"),
+                    end: Some(local("1989-12-01", "11:00:00")),
+                },
+            ]
+        );
+
+        let clockify = "\"Project\",\"Client\",\"Description\",\"Task\",\"Start Date\",\"Start Time\",\"End Date\",\"End Time\"\n\
+                        \"\",\"\",\"Review\",\"\",\"12/01/1989\",\"01:15:00 PM\",\"12/01/1989\",\"02:00:00 PM\"\n";
+        assert_eq!(
+            parse(Format::Clockify, clockify.as_bytes(), Tz::UTC).unwrap(),
+            vec![Entry {
+                work_item: "Review".to_owned(),
+                start: local("1989-12-01", "13:15:00"),
+                end: Some(local("1989-12-01", "14:00:00")),
+            }]
+        );
+
+        let timew = r#"[{"id":2,"start":"19891201T080000Z","end":"19891201T090000Z","tags":["a","b"]},
+                        {"id":1,"start":"19891201T100000Z"}]"#;
+        let entries = parse(Format::Timewarrior, timew.as_bytes(), Tz::UTC).unwrap();
+        assert_eq!(entries[0].work_item, "a, b");
+        assert_eq!(entries[0].start.to_rfc3339(), "1989-12-01T08:00:00+00:00");
+        assert_eq!(entries[1].end, None);
+
+        assert!(parse(Format::Toggl, "Project,Start date\n".as_bytes(), Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn split_at_midnight() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        let entries = vec![
+            Entry {
+                work_item: "night".to_owned(),
+                start: local("1989-12-01", "22:00:00"),
+                end: Some(local("1989-12-03", "01:00:00")),
+            },
+            Entry {
+                work_item: "night".to_owned(),
+                start: local("1989-12-03", "23:00:00"),
+                end: Some(local("1989-12-04", "00:00:00")),
+            },
+        ];
+        let report = import(&db, entries, false).unwrap();
+        assert_eq!(report.imported, 4);
+        assert!(report.conflicts.is_empty());
+        let day = |date: &str| -> Vec<_> {
+            db.get_work_on_date(&NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap())
+                .unwrap()
+                .into_iter()
+                .map(|(item, start)| (item, start.with_timezone(&Utc)))
+                .collect()
+        };
+        let night = Some(db.get_available_work().unwrap()[0].1);
+        assert_eq!(
+            day("1989-12-01"),
+            vec![
+                (night, local("1989-12-01", "22:00:00")),
+                (None, local("1989-12-01", "23:59:59")),
+            ]
+        );
+        assert_eq!(
+            day("1989-12-02"),
+            vec![
+                (night, local("1989-12-02", "00:00:00")),
+                (None, local("1989-12-02", "23:59:59")),
+            ]
+        );
+        assert_eq!(
+            day("1989-12-03"),
+            vec![
+                (night, local("1989-12-03", "00:00:00")),
+                (None, local("1989-12-03", "01:00:00")),
+                (night, local("1989-12-03", "23:00:00")),
+                (None, local("1989-12-03", "23:59:59")),
+            ]
+        );
+        assert!(day("1989-12-04").is_empty());
+    }
+
+    #[test]
+    fn import_entries() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.add_work_item("existing").unwrap();
+        let existing = db.get_available_work().unwrap()[0].1;
+        db.insert_interval(
+            local("1989-12-01", "10:00:00"),
+            Some(local("1989-12-01", "11:00:00")),
+            existing,
+        )
+        .unwrap();
+        let entry = |item: &str, start: &str, end: &str| Entry {
+            work_item: item.to_owned(),
+            start: local("1989-12-01", start),
+            end: Some(local("1989-12-01", end)),
+        };
+        let entries = vec![
+            entry("new", "08:00:00", "09:00:00"),
+            entry("existing", "09:00:00", "10:00:00"),
+            entry("new", "10:30:00", "12:00:00"),
+            entry("new", "08:30:00", "08:45:00"),
+            entry("new", "13:00:00", "12:00:00"),
+        ];
+        let date = NaiveDate::from_ymd_opt(1989, 12, 1).unwrap();
+        let before = db.get_work_on_date(&date).unwrap();
+
+        let report = import(&db, entries.clone(), true).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.new_items, vec!["new".to_owned()]);
+        assert_eq!(
+            report
+                .conflicts
+                .iter()
+                .map(|c| c.entry.clone())
+                .collect::<Vec<_>>(),
+            entries[2..].to_vec()
+        );
+        assert_eq!(db.get_work_on_date(&date).unwrap(), before, "Dry run");
+        assert_eq!(db.get_available_work().unwrap().len(), 1);
+
+        assert_eq!(import(&db, entries, false).unwrap(), report);
+        let new = db
+            .get_available_work()
+            .unwrap()
+            .into_iter()
+            .find(|(name, _)| name == "new")
+            .unwrap()
+            .1;
+        let day: Vec<_> = db
+            .get_work_on_date(&date)
+            .unwrap()
+            .into_iter()
+            .map(|(item, start)| (item, start.with_timezone(&Utc)))
+            .collect();
+        assert_eq!(
+            day,
+            vec![
+                (Some(new), local("1989-12-01", "08:00:00")),
+                (Some(existing), local("1989-12-01", "09:00:00")),
+                (None, local("1989-12-01", "11:00:00")),
+            ]
+        );
+    }
+}
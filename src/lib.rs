@@ -1,2 +1,9 @@
+pub mod auth;
 pub mod business_logic;
+pub mod compliance;
+pub mod config;
 pub mod database;
+pub mod export;
+pub mod idle;
+pub mod import;
+pub mod report;
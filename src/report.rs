@@ -0,0 +1,388 @@
+//! Monthly timesheets for HR: start, end, breaks and worked time per day with month totals
+//! and the balance carried over from the previous months.
+use crate::business_logic::{
+    get_work_time_in_range, update_daily_summaries, work_done_until_now, Error,
+};
+use crate::database::{AbsenceKind, Database, TimeProvider};
+use chrono::{DateTime, Duration, Months, NaiveDate};
+use chrono_tz::Tz;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Markdown,
+    Html,
+    Csv,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Self::Text, Self::Markdown, Self::Html, Self::Csv];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Markdown => "md",
+            Self::Html => "html",
+            Self::Csv => "csv",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Text => "text/plain; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension() == s)
+            .ok_or_else(|| format!("Unknown format '{}'. Use txt, md, html or csv", s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimesheetDay {
+    pub date: NaiveDate,
+    /// First switch of the day
+    pub start: Option<DateTime<Tz>>,
+    /// End of workday, None while running or if it is missing
+    pub end: Option<DateTime<Tz>>,
+    pub breaks: Duration,
+    /// None for days without end of workday and days in the future
+    pub worked: Option<Duration>,
+    /// Expected work, already reduced by absences
+    pub expected: Duration,
+    pub absence: Option<AbsenceKind>,
+}
+
+impl TimesheetDay {
+    pub fn difference(&self) -> Option<Duration> {
+        self.worked.map(|worked| worked - self.expected)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timesheet {
+    /// First day of the month
+    pub month: NaiveDate,
+    pub days: Vec<TimesheetDay>,
+    /// Balance at the start of the month
+    pub carry_over: Duration,
+}
+
+impl Timesheet {
+    pub fn worked(&self) -> Duration {
+        self.days
+            .iter()
+            .filter_map(|day| day.worked)
+            .fold(Duration::zero(), |a, b| a + b)
+    }
+
+    pub fn expected(&self) -> Duration {
+        self.days
+            .iter()
+            .map(|day| day.expected)
+            .fold(Duration::zero(), |a, b| a + b)
+    }
+
+    /// Sum of the differences of all days with known work
+    pub fn difference(&self) -> Duration {
+        self.days
+            .iter()
+            .filter_map(TimesheetDay::difference)
+            .fold(Duration::zero(), |a, b| a + b)
+    }
+
+    /// Balance at the end of the month, or now for the current month
+    pub fn balance(&self) -> Duration {
+        self.carry_over + self.difference()
+    }
+}
+
+/// Timesheet of the month containing `date`
+pub fn timesheet<T: TimeProvider>(db: &Database<T>, date: NaiveDate) -> Result<Timesheet, Error> {
+    let month = date - Duration::days(chrono::Datelike::day0(&date).into());
+    let next_month = month + Months::new(1);
+    let today = db.today()?;
+
+    update_daily_summaries(db)?;
+    let account_start = db
+        .get_kv_optional::<i64>("account_start")?
+        .map(Duration::seconds)
+        .unwrap_or_else(Duration::zero);
+    let carry_over = account_start + db.get_summary_balance(month.min(today))?;
+
+    let mut days = Vec::new();
+    for (date, day) in get_work_time_in_range(db, month, next_month)? {
+        let times = db.get_work_on_date(&date)?;
+        let worked = match date.cmp(&today) {
+            std::cmp::Ordering::Less => day.work_done.ok(),
+            std::cmp::Ordering::Equal => work_done_until_now(db, date).ok(),
+            std::cmp::Ordering::Greater => None,
+        };
+        let start = times.first().map(|(_, start)| *start);
+        let end = match times.last() {
+            Some((None, end)) => Some(*end),
+            _ => None,
+        };
+        let span = match (start, end) {
+            (Some(start), Some(end)) => Some(end - start),
+            (Some(start), None) if date == today => Some(db.local_time(&db.now())? - start),
+            _ => None,
+        };
+        days.push(TimesheetDay {
+            date,
+            start,
+            end,
+            breaks: span
+                .zip(worked)
+                .map(|(span, worked)| span - worked)
+                .unwrap_or_else(Duration::zero),
+            worked,
+            expected: day.expected,
+            absence: day.absence.map(|absence| absence.kind),
+        });
+    }
+    Ok(Timesheet {
+        month,
+        days,
+        carry_over,
+    })
+}
+
+fn format_duration(duration: Duration) -> String {
+    let sign = if duration < Duration::zero() { "-" } else { "" };
+    let minutes = duration.num_minutes().abs();
+    format!("{}{}:{:02}", sign, minutes / 60, minutes % 60)
+}
+
+const HEADER: [&str; 9] = [
+    "Date",
+    "Day",
+    "Start",
+    "End",
+    "Breaks",
+    "Worked",
+    "Expected",
+    "Difference",
+    "Absence",
+];
+
+fn rows(timesheet: &Timesheet) -> Vec<[String; 9]> {
+    let time =
+        |t: Option<DateTime<Tz>>| t.map(|t| t.format("%H:%M").to_string()).unwrap_or_default();
+    let duration = |d: Option<Duration>| d.map(format_duration).unwrap_or_default();
+    timesheet
+        .days
+        .iter()
+        .map(|day| {
+            [
+                day.date.to_string(),
+                day.date.format("%a").to_string(),
+                time(day.start),
+                time(day.end),
+                duration(day.start.and(day.worked).map(|_| day.breaks)),
+                duration(day.worked),
+                format_duration(day.expected),
+                duration(day.difference()),
+                day.absence
+                    .map(|absence| absence.name().to_owned())
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+/// Month totals in the layout of the day rows, followed by carry-over and balance
+fn summary(timesheet: &Timesheet) -> Vec<[String; 9]> {
+    let row = |label: &str, worked: String, expected: String, difference: Duration| {
+        [
+            label.to_owned(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            worked,
+            expected,
+            format_duration(difference),
+            String::new(),
+        ]
+    };
+    vec![
+        row(
+            "Total",
+            format_duration(timesheet.worked()),
+            format_duration(timesheet.expected()),
+            timesheet.difference(),
+        ),
+        row(
+            "Carry-over",
+            String::new(),
+            String::new(),
+            timesheet.carry_over,
+        ),
+        row("Balance", String::new(), String::new(), timesheet.balance()),
+    ]
+}
+
+fn title(timesheet: &Timesheet) -> String {
+    format!("Timesheet {}", timesheet.month.format("%Y-%m"))
+}
+
+fn render_text(timesheet: &Timesheet) -> String {
+    let mut lines = vec![title(timesheet), String::new()];
+    let line = |row: &[String]| {
+        format!(
+            "{:<10} {:<3} {:>5} {:>5} {:>6} {:>6} {:>6} {:>7} {}",
+            row[0], row[1], row[2], row[3], row[4], row[5], row[6], row[7], row[8]
+        )
+        .trim_end()
+        .to_owned()
+    };
+    lines.push(line(&HEADER.map(str::to_owned)));
+    lines.extend(rows(timesheet).iter().map(|row| line(row)));
+    lines.push(String::new());
+    lines.extend(summary(timesheet).iter().map(|row| line(row)));
+    lines.join("\n") + "\n"
+}
+
+fn render_markdown(timesheet: &Timesheet) -> String {
+    let line = |row: &[String]| format!("| {} |", row.join(" | "));
+    let mut lines = vec![format!("# {}", title(timesheet)), String::new()];
+    lines.push(line(&HEADER.map(str::to_owned)));
+    lines.push(format!("|{}", "---|".repeat(HEADER.len())));
+    lines.extend(rows(timesheet).iter().map(|row| line(row)));
+    lines.extend(
+        summary(timesheet)
+            .iter()
+            .map(|row| line(&row.clone().map(|cell| bold(&cell)))),
+    );
+    lines.join("\n") + "\n"
+}
+
+fn bold(cell: &str) -> String {
+    if cell.is_empty() {
+        String::new()
+    } else {
+        format!("**{}**", cell)
+    }
+}
+
+fn render_html(timesheet: &Timesheet) -> String {
+    let cells = |tag: &str, row: &[String]| {
+        row.iter()
+            .map(|cell| format!("<{0}>{1}</{0}>", tag, cell))
+            .collect::<String>()
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+         <body>\n<h1>{0}</h1>\n<table>\n<thead><tr>{1}</tr></thead>\n<tbody>\n",
+        title(timesheet),
+        cells("th", &HEADER.map(str::to_owned))
+    );
+    for row in rows(timesheet) {
+        html.push_str(&format!("<tr>{}</tr>\n", cells("td", &row)));
+    }
+    html.push_str("</tbody>\n<tfoot>\n");
+    for row in summary(timesheet) {
+        html.push_str(&format!("<tr>{}</tr>\n", cells("th", &row)));
+    }
+    html.push_str("</tfoot>\n</table>\n</body>\n</html>\n");
+    html
+}
+
+fn render_csv(timesheet: &Timesheet) -> String {
+    let mut csv = csv::Writer::from_writer(Vec::new());
+    let records = std::iter::once(HEADER.map(str::to_owned))
+        .chain(rows(timesheet))
+        .chain(summary(timesheet));
+    for record in records {
+        csv.write_record(&record)
+            .expect("Writing to memory doesn't fail");
+    }
+    String::from_utf8(csv.into_inner().expect("Writing to memory doesn't fail"))
+        .expect("All fields are UTF-8")
+}
+
+pub fn render(timesheet: &Timesheet, format: Format) -> String {
+    match format {
+        Format::Text => render_text(timesheet),
+        Format::Markdown => render_markdown(timesheet),
+        Format::Html => render_html(timesheet),
+        Format::Csv => render_csv(timesheet),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, timesheet, Format};
+    use crate::database::{tests::MockTime, Absence, AbsenceKind, Database, TimeProvider};
+    use chrono::{Duration, NaiveDate};
+
+    #[test]
+    fn monthly_timesheet() {
+        let t = MockTime::new();
+        let db = Database::open(":memory:", &t).unwrap();
+        db.set_holiday_region(None).unwrap();
+        db.set_kv("account_start", 3600).unwrap();
+        db.add_work_item("a").unwrap();
+        let a = db.get_available_work().unwrap()[0].1;
+        // A Sunday with one hour overtime
+        t.advance(-24);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(1);
+        db.set_current_work(None).unwrap();
+        // The first of January, with a break
+        t.advance(23);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(3);
+        db.set_current_work(None).unwrap();
+        t.advance(1);
+        db.set_current_work(Some(a)).unwrap();
+        t.advance(3);
+        db.set_current_work(None).unwrap();
+        let second = NaiveDate::from_ymd_opt(1990, 1, 2).unwrap();
+        db.set_absence(
+            second,
+            Some(&Absence {
+                kind: AbsenceKind::Sick,
+                half_day: false,
+            }),
+        )
+        .unwrap();
+        t.advance(24);
+
+        let today = t.now().date_naive();
+        let sheet = timesheet(&db, today).unwrap();
+        assert_eq!(sheet.month, NaiveDate::from_ymd_opt(1990, 1, 1).unwrap());
+        assert_eq!(sheet.days.len(), 31);
+        assert_eq!(sheet.carry_over, Duration::hours(2));
+        let first = &sheet.days[0];
+        assert_eq!(first.breaks, Duration::hours(1));
+        assert_eq!(first.worked, Some(Duration::hours(6)));
+        assert_eq!(first.difference(), Some(Duration::hours(-1)));
+        assert_eq!(sheet.days[1].absence, Some(AbsenceKind::Sick));
+        assert_eq!(sheet.days[1].difference(), Some(Duration::zero()));
+        assert_eq!(sheet.days[2].worked, None, "Future days have no work yet");
+        assert_eq!(sheet.worked(), Duration::hours(6));
+        assert_eq!(sheet.balance(), Duration::hours(1));
+
+        let text = render(&sheet, Format::Text);
+        assert!(text.contains("1990-01-01 Mon 09:00 16:00   1:00   6:00   7:00   -1:00"));
+        assert!(text.contains(&format!("Balance{}1:00\n", " ".repeat(44))));
+        let markdown = render(&sheet, Format::Markdown);
+        assert!(markdown.contains("| 1990-01-02 | Tue |  |  |  | 0:00 | 0:00 | 0:00 | sick |"));
+        assert!(render(&sheet, Format::Html).contains("<td>sick</td>"));
+        let csv = render(&sheet, Format::Csv);
+        assert_eq!(csv.lines().count(), 1 + 31 + 3);
+        assert!(csv.ends_with("Balance,,,,,,,1:00,\n"));
+    }
+}